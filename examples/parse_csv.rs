@@ -1,5 +1,6 @@
 use std::{error::Error, io, process};
 
+#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize)]
 struct Record {
     市: String,
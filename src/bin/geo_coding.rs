@@ -1,19 +1,30 @@
 use std::{
-    io::stdout,
-    sync::{Arc, Mutex},
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::File,
+    future::Future,
+    io::{stdout, BufWriter, Write as _},
+    sync::Arc,
+    time::Duration,
 };
 
-use anyhow::{Ok, Result};
+use anyhow::{Context, Ok, Result};
 use clap::{Parser, Subcommand};
+use console::Style;
 use poi_cli::{
-    cli::{parse_key_value, KeyVal},
-    process_error_output, ExtraArgs, GeoCodingConfig, LoadConfig,
+    cell_text,
+    cli::{parse_duration, parse_key_value, KeyVal},
+    diff_json, distance_in_metres, process_error_output, Aborted, ApiError, Cache, Chain,
+    ChainAnswer, Checkpoint, CoordSystem, CoordinateFields, DiffEntry, Division, DivisionLevel,
+    ExtraArgs, Gazetteer, GeoCodingConfig, GeoCodingProfile, Input, InputOptions, LoadConfig,
+    OrderedSink, OutOfRegion, OutputFormat, OutputSink, Progress, Quality, Recorder, Region,
+    Regions, Replay, Report, RequestStats, ResolvedRequest, Row, RowError,
 };
-use serde_json::{json, Map};
+use serde_json::json;
 use serde_json_lodash::get;
 use tokio::task::JoinSet;
 
-/// Diff two http requests and compare the difference of the responses
+/// Geocode addresses, check profiles and diff the responses of two profiles
 #[derive(Parser, Debug, Clone)]
 #[clap(version = "0.1.0", author = "Misky <fengwei5@foxmail.com>")]
 pub struct Args {
@@ -28,9 +39,91 @@ enum Action {
     Query(QueryArgs),
 
     /// Query all
-    QueryAll(QueryAllArgs),
+    QueryAll(Box<QueryAllArgs>),
+
+    /// Run profiles and assert the expectations declared in their `check` section.
+    /// Exits with 1 when an expectation fails and 2 when a request could not be made.
+    Check(CheckArgs),
+
+    /// Send the same address through two profiles and diff the responses
+    Diff(DiffArgs),
+
+    /// Inspect or clear the response cache
+    Cache(CacheArgs),
+}
+
+/// Options on how requests are sent, shared by the query commands
+#[derive(Parser, Debug, Clone)]
+struct SendOpts {
+    /// Always send requests, don't read or write the response cache
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Ignore cached responses but store the fresh ones
+    #[clap(long)]
+    refresh: bool,
+
+    /// The directory responses are cached in
+    #[clap(long, value_parser, default_value = DEFAULT_CACHE_DIR)]
+    cache_dir: String,
+
+    /// How long cached responses stay valid, e.g. `3600`, `12h` or `30d`. Forever if omitted
+    #[clap(long, value_parser = parse_duration)]
+    cache_ttl: Option<Duration>,
+
+    /// Append every request sent and its response to this JSON lines file
+    #[clap(long, value_parser)]
+    record: Option<String>,
+
+    /// Answer requests from a file written by `--record` instead of the network
+    #[clap(long, value_parser, conflicts_with = "record")]
+    replay: Option<String>,
+
+    /// Print every request, with the extra args merged in, instead of sending it
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Print every request as a curl command instead of sending it
+    #[clap(long)]
+    print_curl: bool,
 }
 
+#[derive(Parser, Debug, Clone)]
+struct CacheArgs {
+    #[clap(subcommand)]
+    action: CacheAction,
+
+    /// The directory responses are cached in
+    #[clap(long, value_parser, default_value = DEFAULT_CACHE_DIR, global = true)]
+    cache_dir: String,
+
+    /// How long cached responses stay valid, used to tell expired entries apart
+    #[clap(long, value_parser = parse_duration, global = true)]
+    cache_ttl: Option<Duration>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum CacheAction {
+    /// Print the number of entries, expired entries and bytes used
+    Stats,
+
+    /// Remove cached responses
+    Purge {
+        /// Only remove expired entries
+        #[clap(long)]
+        expired: bool,
+    },
+
+    /// Write every cached response as JSON lines
+    Export {
+        /// The output file, defaults to stdout
+        #[clap(short, long, value_parser)]
+        output: Option<String>,
+    },
+}
+
+const DEFAULT_CACHE_DIR: &str = ".poi_cache";
+
 #[derive(Parser, Debug, Clone)]
 struct QueryArgs {
     /// The profile name
@@ -48,8 +141,24 @@ struct QueryArgs {
     #[clap(short, long, value_parser)]
     config: Option<String>,
 
+    /// The output file, defaults to `result.<format>`
     #[clap(short, long, value_parser)]
     output: Option<String>,
+
+    /// The output format
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Convert the picked coordinates into this system, the profile must set `res.coordinates.system`
+    #[clap(long, value_enum)]
+    coords: Option<CoordSystem>,
+
+    /// Fetch at most this many pages of a paginated search, overriding the profile's `max_pages`
+    #[clap(long, value_parser)]
+    max_pages: Option<u64>,
+
+    #[clap(flatten)]
+    send: SendOpts,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -69,8 +178,164 @@ struct QueryAllArgs {
     #[clap(short, long, value_parser)]
     config: Option<String>,
 
+    /// The input table, a CSV file or a spreadsheet (.xlsx, .xls, .ods)
     #[clap(short, long, value_parser)]
     input: Option<String>,
+
+    /// The worksheet to read from a spreadsheet, defaults to the first one
+    #[clap(long, value_parser)]
+    sheet: Option<String>,
+
+    /// The 1-based row holding the column headers
+    #[clap(long, value_parser, default_value_t = 1)]
+    header_row: usize,
+
+    /// The column holding the address, a header name or a 0-based index
+    #[clap(long, value_parser, default_value = "0")]
+    address_column: String,
+
+    /// The column holding the parent region of the address, filled into `{parent}` in
+    /// the profile's address templates
+    #[clap(long, value_parser)]
+    parent_column: Option<String>,
+
+    /// Reverse geocode the coordinate in this column and `--lat-column` instead of an address
+    #[clap(long, value_parser, requires = "lat_column")]
+    lng_column: Option<String>,
+
+    /// The column holding the latitude when reverse geocoding
+    #[clap(long, value_parser, requires = "lng_column")]
+    lat_column: Option<String>,
+
+    /// The coordinate system of the input coordinates, defaults to the one the profile expects
+    #[clap(long, value_enum, requires = "lng_column")]
+    input_coords: Option<CoordSystem>,
+
+    /// Look addresses up in the bundled GB/T 2260 divisions: fill `{parent}` with the
+    /// regions above them, add 省份/城市/区县 columns and check the returned adcode
+    #[clap(long, value_parser, conflicts_with = "lng_column")]
+    gazetteer: bool,
+
+    /// Check that each returned point falls inside its row's region in this GeoJSON file,
+    /// adding `in_region` and `distance_to_region_m` columns
+    #[clap(long, value_parser, conflicts_with = "lng_column")]
+    regions: Option<String>,
+
+    /// Fail rows outside their region as `out_of_region` instead of writing them with
+    /// `in_region` false
+    #[clap(long, requires = "regions")]
+    reject_outside: bool,
+
+    /// The column naming each row's region, by name or adcode, defaults to the address column
+    #[clap(long, value_parser, requires = "regions")]
+    region_column: Option<String>,
+
+    /// The coordinate system of the region polygons
+    #[clap(long, value_enum, default_value = "wgs84", requires = "regions")]
+    regions_coords: CoordSystem,
+
+    /// Input columns copied into each output row, header names or 0-based indexes
+    #[clap(long, value_parser, value_delimiter = ',')]
+    keep_columns: Vec<String>,
+
+    /// Write rows in input order instead of as they complete
+    #[clap(long)]
+    ordered: bool,
+
+    /// Record completed rows to this file, a restarted run skips the rows found in it
+    #[clap(long, value_parser)]
+    checkpoint: Option<String>,
+
+    /// Write failed rows to this CSV file, with the error kind, http status, message and attempts
+    #[clap(long, value_parser)]
+    rejects: Option<String>,
+
+    /// Write rows whose `quality` is low, and failed rows, to this CSV file instead of the output
+    #[clap(long, value_parser)]
+    review: Option<String>,
+
+    /// How many times to retry a row after a network or unknown provider error
+    #[clap(long, value_parser, default_value_t = 0)]
+    retries: u32,
+
+    /// Don't report progress on stderr
+    #[clap(long)]
+    no_progress: bool,
+
+    /// Write a JSON summary of the run to this file, or to stderr when no file is given
+    #[clap(long, value_parser, num_args = 0..=1, default_missing_value = "-")]
+    report: Option<String>,
+
+    /// The output file, defaults to stdout
+    #[clap(short, long, value_parser)]
+    output: Option<String>,
+
+    /// The output format
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Convert the picked coordinates into this system, the profile must set `res.coordinates.system`
+    #[clap(long, value_enum)]
+    coords: Option<CoordSystem>,
+
+    #[clap(flatten)]
+    send: SendOpts,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct CheckArgs {
+    /// The profile name, all profiles with a `check` section are run if omitted
+    #[clap(short, long, value_parser)]
+    profile: Option<String>,
+
+    /// Overrides args. Could be used to override the query, headers and body of the request.
+    /// for query params, use `-e key=value`
+    /// for headers, use `-e %key=value`
+    /// for body, use `-e @key=value`
+    #[clap(short, long, value_parser = parse_key_value, number_of_values = 1)]
+    extra_params: Vec<KeyVal>,
+
+    /// Configuration to use
+    #[clap(short, long, value_parser)]
+    config: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct DiffArgs {
+    /// The first profile name
+    #[clap(value_parser)]
+    profile1: String,
+
+    /// The second profile name
+    #[clap(value_parser)]
+    profile2: String,
+
+    /// The address sent to both profiles, each in the param of its `address` profile
+    #[clap(short, long, value_parser)]
+    address: Option<String>,
+
+    /// Diff the full response bodies (minus `skip_body`) instead of the picked results
+    #[clap(long)]
+    full: bool,
+
+    /// Path of the longitude in the response bodies
+    #[clap(long, value_parser, default_value = "result.location.lng")]
+    lng_path: String,
+
+    /// Path of the latitude in the response bodies
+    #[clap(long, value_parser, default_value = "result.location.lat")]
+    lat_path: String,
+
+    /// Overrides args. Could be used to override the query, headers and body of the request.
+    /// for query params, use `-e key=value`
+    /// for headers, use `-e %key=value`
+    /// for body, use `-e @key=value`
+    #[clap(short, long, value_parser = parse_key_value, number_of_values = 1)]
+    extra_params: Vec<KeyVal>,
+
+    /// Configuration to use
+    #[clap(short, long, value_parser)]
+    config: Option<String>,
 }
 
 #[tokio::main]
@@ -79,16 +344,34 @@ async fn main() -> Result<()> {
 
     let result = match args.action {
         Action::Query(args) => query(args).await,
-        Action::QueryAll(args) => query_all(args).await,
+        Action::QueryAll(args) => query_all(*args).await,
+        Action::Check(args) => match check(args).await {
+            Result::Ok(true) => Ok(()),
+            Result::Ok(false) => std::process::exit(1),
+            Err(e) => {
+                process_error_output(Err(e))?;
+                std::process::exit(2);
+            }
+        },
+        Action::Diff(args) => diff(args).await,
+        Action::Cache(args) => cache(args),
         // _ => panic!("Not implemented yet"),
     };
 
-    process_error_output(result)
+    let failed = result.is_err();
+    process_error_output(result)?;
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
 }
 
 async fn query(args: QueryArgs) -> Result<()> {
     let config_file = args.config.unwrap_or_else(|| "./poi.yaml".to_string());
-    let config = GeoCodingConfig::load_yaml(&config_file).await?;
+    let config = args
+        .send
+        .apply(GeoCodingConfig::load_yaml(&config_file).await?)?;
 
     let profile = config.get_profile(&args.profile).ok_or_else(|| {
         anyhow::anyhow!(
@@ -98,8 +381,48 @@ async fn query(args: QueryArgs) -> Result<()> {
         )
     })?;
 
+    let mut profile = profile.clone();
+    if let (Some(max_pages), Some(paginate)) = (args.max_pages, profile.req.paginate.as_mut()) {
+        paginate.max_pages = max_pages;
+    }
+    let profile = &profile;
     let extra_args = ExtraArgs::from(args.extra_params);
 
+    if args.send.is_dry_run() {
+        args.send.print_request(&profile.req.resolve(&extra_args)?);
+        return Ok(());
+    }
+
+    let output = args
+        .output
+        .unwrap_or_else(|| format!("result.{}", args.format.extension()));
+    let mut sink = args
+        .format
+        .sink(open_output(Some(&output))?, profile.res.coordinates.clone())?;
+
+    // a paginated search writes a row per result as each page arrives
+    if profile.req.paginate.is_some() {
+        let mut count = 0;
+        let pages = profile
+            .query_pages(extra_args, |results| {
+                for result in &results {
+                    let mut row = Row::new();
+                    row.extend(picked_results(&args.profile, result)?);
+                    if let Some(to) = args.coords {
+                        row.convert_coordinates(coordinate_fields(profile)?, to)?;
+                    }
+                    sink.write_row(&row)?;
+                }
+                count += results.len();
+                Ok(())
+            })
+            .await?;
+        sink.finish()?;
+
+        println!("Done: {} results from {} pages", count, pages);
+        return Ok(());
+    }
+
     let result = profile.query(extra_args).await?;
 
     let query = &profile.req.params.clone().unwrap_or_else(|| json!({}));
@@ -107,103 +430,1123 @@ async fn query(args: QueryArgs) -> Result<()> {
     let address = get!(json!(query), json!("address"));
     let address = address.as_str().unwrap_or("北京市人民政府");
 
-    let mut wtr = csv::Writer::from_path(&args.output.clone().unwrap_or("result.csv".into()))?;
-
-    let obj = result.as_object().unwrap();
-    let first_record = String::from("地址");
-    let mut records = obj.keys().collect::<Vec<_>>();
-    records.insert(0, &first_record);
-    wtr.write_record(records)?;
-
-    write_records_to_csv(&mut wtr, &obj, address)?;
+    let mut row = Row::new();
+    row.push("地址", address);
+    row.extend(picked_results(address, &result)?);
+    if let Some(to) = args.coords {
+        row.convert_coordinates(coordinate_fields(profile)?, to)?;
+    }
+    sink.write_row(&row)?;
+    sink.finish()?;
 
-    wtr.flush()?;
     println!("Done");
     Ok(())
 }
 
 async fn query_all(args: QueryAllArgs) -> Result<()> {
     let config_file = args.config.unwrap_or_else(|| "./poi.yaml".to_string());
-    let config = GeoCodingConfig::load_yaml(&config_file).await?;
-    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+    let stats = Arc::new(RequestStats::default());
+    let config = args
+        .send
+        .apply(GeoCodingConfig::load_yaml(&config_file).await?)?
+        .with_stats(Some(stats.clone()));
+    let chain = config.get_chain(&args.profile).ok_or_else(|| {
         anyhow::anyhow!(
             "Profile {} not found in config file {}",
             args.profile,
             config_file
         )
     })?;
+    // a chain records which of its profiles answered each row
+    let is_chain = config.chains.contains_key(&args.profile);
+    let profile = chain.first();
     let extra_args = ExtraArgs::from(args.extra_params);
 
-    let mut rdr =
-        csv::Reader::from_path(args.input.unwrap_or("fixtures/resident_city.csv".into()))?;
-    let records = rdr.records();
+    let options = InputOptions {
+        sheet: args.sheet,
+        header_row: args.header_row,
+    };
+    let input = Input::from_path(
+        args.input.unwrap_or("fixtures/resident_city.csv".into()),
+        &options,
+    )?;
+    let columns = match (&args.lng_column, &args.lat_column) {
+        (Some(lng), Some(lat)) => QueryColumns::Location {
+            lng: input.column_index(lng)?,
+            lat: input.column_index(lat)?,
+            system: args.input_coords,
+        },
+        _ => QueryColumns::Address {
+            address: input.column_index(&args.address_column)?,
+            parent: args
+                .parent_column
+                .as_deref()
+                .map(|column| input.column_index(column))
+                .transpose()?,
+            gazetteer: args.gazetteer.then(Gazetteer::bundled),
+        },
+    };
+
+    let batch_size = batch_size(&chain, is_chain, &columns)?;
+
+    if args.send.is_dry_run() {
+        match batch_size {
+            Some(size) => {
+                for (i, records) in input.records.chunks(size).enumerate() {
+                    let addresses = records
+                        .iter()
+                        .map(|record| columns.address(record))
+                        .collect::<Vec<_>>();
+                    match profile.resolve_batch(extra_args.clone(), &addresses) {
+                        Result::Ok(req) => args.send.print_request(&req),
+                        Err(e) => eprintln!("batch {}: {:#}", i + 1, e),
+                    }
+                }
+            }
+            None => {
+                for (i, record) in input.records.iter().enumerate() {
+                    match columns.resolve(profile, extra_args.clone(), record) {
+                        Result::Ok(req) => args.send.print_request(&req),
+                        Err(e) => eprintln!("row {}: {:#}", i + 1, e),
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
 
     let mut set = JoinSet::new();
 
-    let wtr = csv::Writer::from_writer(stdout());
-    let wtr = Arc::new(Mutex::new(wtr));
+    let sink = args.format.sink(
+        open_output(args.output.as_deref())?,
+        profile.res.coordinates.clone(),
+    )?;
+    let mut sink = if args.ordered {
+        Output::Ordered(OrderedSink::new(sink, 1))
+    } else {
+        Output::Unordered(sink)
+    };
 
-    let has_head = Arc::new(Mutex::new(false));
+    let keep_columns = args
+        .keep_columns
+        .iter()
+        .map(|column| input.column_index(column))
+        .collect::<Result<Vec<_>>>()?;
+    let keep_columns = Arc::new(keep_columns);
+    if args.coords.is_some() || args.regions.is_some() {
+        for (_, profile) in &chain.links {
+            coordinate_fields(profile)?;
+        }
+    }
+    let regions = args
+        .regions
+        .as_deref()
+        .map(Regions::open)
+        .transpose()?
+        .map(Arc::new);
+    let region_column = args
+        .region_column
+        .as_deref()
+        .map(|column| input.column_index(column))
+        .transpose()?;
+    let headers = Arc::new(input.headers);
 
-    for record in records {
-        let record = record?;
-        let address = record.get(0).unwrap().to_owned();
-        let extra_args = extra_args.clone();
-        let profile = profile.clone();
+    let mut builder = RowBuilder {
+        columns,
+        headers: headers.clone(),
+        keep_columns: keep_columns.clone(),
+        is_chain,
+        coords: args.coords,
+        regions,
+        regions_coords: args.regions_coords,
+        region_column,
+        reject_outside: args.reject_outside,
+        template: None,
+    };
+    // the links of a chain pick different columns and a division check only sometimes
+    // grades a row, the first row mustn't decide the header
+    let template = builder.template(&chain);
+    builder.template = Some(template.clone());
+    let builder = Arc::new(builder);
 
-        let wtr = wtr.clone();
-        let has_head = has_head.clone();
+    let mut review = match args.review.as_deref() {
+        Some(path) => {
+            let sink = OutputFormat::Csv.sink(open_output(Some(path))?, None)?;
+            let mut template = template;
+            template.push("quality", json!(null));
+            template.push("message", json!(null));
+            Some(Review { sink, template })
+        }
+        None => None,
+    };
 
-        set.spawn(async move {
-            let result = profile
-                .query_with_city(extra_args, &address)
-                .await
-                .expect(format!("failed to query {}", address).as_str());
+    let mut checkpoint = args.checkpoint.map(Checkpoint::open).transpose()?;
+    if let Some(checkpoint) = checkpoint.as_ref().filter(|c| !c.is_empty()) {
+        eprintln!("resuming, {} rows already completed", checkpoint.len());
+    }
+
+    let retries = args.retries;
+    // rows waiting for their batch to fill up
+    let mut pending = vec![];
+
+    let level_field = profile.res.level.as_deref();
+    let mut report = Report::default();
+    let mut progress =
+        (!args.no_progress).then(|| Progress::new(input.records.len(), stats.clone()));
+    // rows sent off but not done yet, the ones left after a fatal error count as aborted
+    let mut in_flight = BTreeMap::new();
 
-            let mut has_head = has_head.lock().unwrap();
-            let obj = result.as_object().unwrap();
-            let mut wtr = wtr.lock().unwrap();
+    for (i, record) in input.records.into_iter().enumerate() {
+        let row_number = i + 1;
+        let query = columns.text(&record);
 
-            if has_head.eq(&false) {
-                *has_head = true;
+        let done = checkpoint
+            .as_ref()
+            .and_then(|c| c.get(&Checkpoint::key(row_number, &query)));
+        if let Some(row) = done {
+            route(&mut sink, review.as_mut(), row_number, row.clone())?;
+            report.resume();
+            if let Some(progress) = progress.as_mut() {
+                progress.resume(1);
+            }
+            continue;
+        }
+        in_flight.insert(row_number, (query.clone(), record.clone()));
 
-                let first_record = String::from("地址");
-                let mut records = obj.keys().collect::<Vec<_>>();
-                records.insert(0, &first_record);
-                wtr.write_record(records).unwrap();
+        if let Some(size) = batch_size {
+            pending.push((row_number, query, record));
+            if pending.len() == size {
+                let rows = std::mem::take(&mut pending);
+                spawn_batch(
+                    &mut set,
+                    &chain,
+                    &builder,
+                    extra_args.clone(),
+                    rows,
+                    retries,
+                );
             }
+            continue;
+        }
+
+        let mut extra_args = extra_args.clone();
+        extra_args.row_id = Some(row_number.to_string());
+        let (chain, builder) = (chain.clone(), builder.clone());
+
+        set.spawn(async move {
+            let query_row = || builder.columns.query(&chain, extra_args.clone(), &record);
+            let (attempts, result) = with_retries(retries, query_row).await;
+            let row = result.and_then(|answer| builder.build(row_number, &query, &record, &answer));
 
-            write_records_to_csv(&mut wtr, &obj, &address).unwrap();
+            vec![RowOutcome {
+                row_number,
+                query,
+                record,
+                attempts,
+                row,
+            }]
         });
     }
+    if !pending.is_empty() {
+        spawn_batch(&mut set, &chain, &builder, extra_args, pending, retries);
+    }
+
+    let mut rejects = args
+        .rejects
+        .as_deref()
+        .map(|path| OutputFormat::Csv.sink(open_output(Some(path))?, None))
+        .transpose()?;
+    let mut fatal = None;
+
+    loop {
+        let outcomes = match set.join_next().await {
+            Some(Result::Ok(outcomes)) => outcomes,
+            // aborted after a fatal error, its rows are drained below
+            Some(Err(e)) if e.is_cancelled() => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None if !in_flight.is_empty() => std::mem::take(&mut in_flight)
+                .into_iter()
+                .map(|(row_number, (query, record))| RowOutcome {
+                    row_number,
+                    query,
+                    record,
+                    attempts: 0,
+                    row: Err(Aborted.into()),
+                })
+                .collect(),
+            None => break,
+        };
+
+        for outcome in outcomes {
+            in_flight.remove(&outcome.row_number);
+            let e = match outcome.row {
+                Result::Ok(row) => {
+                    if let Some(checkpoint) = checkpoint.as_mut() {
+                        checkpoint
+                            .record(&Checkpoint::key(outcome.row_number, &outcome.query), &row)?;
+                    }
+                    let level = level_field.and_then(|field| row.get(field)).map(cell_text);
+                    report.success(level.as_deref());
+                    route(&mut sink, review.as_mut(), outcome.row_number, row)?;
+                    if let Some(progress) = progress.as_mut() {
+                        progress.inc(true);
+                    }
+                    continue;
+                }
+                Err(e) => e,
+            };
+            sink.skip(outcome.row_number)?;
+
+            let error = RowError::from_error(&e);
+            let e = e.context(format!("failed to query {}", outcome.query));
+            report.failure(&error);
+            if let Some(progress) = progress.as_mut() {
+                progress.inc(false);
+            }
+
+            if let Some(rejects) = rejects.as_mut() {
+                let mut row = Row::new();
+                row.push("行号", outcome.row_number);
+                for (header, field) in headers.iter().zip(outcome.record.iter()) {
+                    row.push(header, field);
+                }
+                row.push("error_kind", error.kind.as_str());
+                row.push("http_status", error.http_status);
+                row.push("message", error.message.as_str());
+                row.push("attempts", outcome.attempts);
+                rejects.write_row(&row)?;
+            }
+
+            if let Some(review) = review.as_mut() {
+                let mut row = Row::new();
+                row.push("行号", outcome.row_number);
+                columns.push(&mut row, &outcome.record);
+                for &i in keep_columns.iter() {
+                    row.push(&headers[i], outcome.record.get(i).unwrap_or_default());
+                }
+                row.push("quality", Quality::Failed.as_str());
+                row.push("message", error.message.as_str());
+                review.write(&row)?;
+            }
+
+            // no point in spending more requests once the key or quota is gone
+            let is_fatal = e
+                .downcast_ref::<ApiError>()
+                .is_some_and(|api_error| api_error.kind.is_fatal());
+            if is_fatal && fatal.is_none() {
+                set.abort_all();
+                fatal = Some(e);
+            } else if rejects.is_none() && error.kind != "aborted" {
+                eprintln!("{:#}", e);
+            }
+        }
+    }
+    if let Some(progress) = progress.as_mut() {
+        progress.finish();
+    }
+    sink.finish()?;
+    if let Some(rejects) = rejects.as_mut() {
+        rejects.finish()?;
+    }
+    if let Some(review) = review.as_mut() {
+        review.sink.finish()?;
+    }
 
-    while let Some(_) = set.join_next().await {}
-    let mut wtr = wtr.lock().unwrap();
-    wtr.flush()?;
+    report.requests(&stats.latencies());
+    report.finish();
+    if let Some(path) = args.report.as_deref() {
+        let json = serde_json::to_string_pretty(&report)?;
+        match path {
+            "-" => eprintln!("{}", json),
+            path => std::fs::write(path, json + "\n")?,
+        }
+    }
+
+    if let Some(e) = fatal {
+        return Err(e);
+    }
+
+    if report.failed > 0 {
+        let by_kind = report
+            .errors_by_kind
+            .iter()
+            .map(|(kind, n)| format!("{}: {}", kind, n))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(anyhow::anyhow!(
+            "{} rows succeeded, {} rows failed ({})",
+            report.succeeded,
+            report.failed,
+            by_kind
+        ));
+    }
 
     Ok(())
 }
 
-fn write_records_to_csv<T: std::io::Write>(
-    wtr: &mut csv::Writer<T>,
-    obj: &Map<String, serde_json::Value>,
-    address: &str,
+/// Builds the output row of an answer
+struct RowBuilder {
+    columns: QueryColumns,
+    headers: Arc<csv::StringRecord>,
+    keep_columns: Arc<Vec<usize>>,
+    /// A chain records which of its profiles answered each row
+    is_chain: bool,
+    coords: Option<CoordSystem>,
+    regions: Option<Arc<Regions>>,
+    regions_coords: CoordSystem,
+    region_column: Option<usize>,
+    reject_outside: bool,
+    /// Every column a row can have, rows start from it
+    template: Option<Row>,
+}
+
+impl RowBuilder {
+    fn build(
+        &self,
+        row_number: usize,
+        query: &str,
+        record: &csv::StringRecord,
+        answer: &ChainAnswer,
+    ) -> Result<Row> {
+        let mut row = self.template.clone().unwrap_or_default();
+        row.push("行号", row_number);
+        self.columns.push(&mut row, record);
+        for &i in self.keep_columns.iter() {
+            row.push(&self.headers[i], record.get(i).unwrap_or_default());
+        }
+        row.extend(picked_results(query, &answer.results)?);
+        if self.is_chain {
+            row.push("服务商", answer.name);
+            row.push("accepted", answer.accepted);
+        }
+        if let Some((gazetteer, input)) = self.columns.division(record) {
+            let adcode = answer.profile.res.adcode.as_deref();
+            let adcode = adcode.and_then(|field| answer.results.get(field));
+            push_divisions(&mut row, gazetteer, input, adcode);
+        }
+        if let Some(regions) = self.regions.as_deref() {
+            let region = match self.region_column {
+                Some(i) => regions.get(record.get(i).unwrap_or_default()),
+                None => self
+                    .columns
+                    .division(record)
+                    .and_then(|(_, division)| division)
+                    .and_then(|division| regions.get(&division.code.to_string()))
+                    .or_else(|| regions.get(query)),
+            };
+            let fields = coordinate_fields(answer.profile)?;
+            let (to, reject) = (self.regions_coords, self.reject_outside);
+            check_region(&mut row, region, fields, to, reject)?;
+        }
+        if let Some(to) = self.coords {
+            row.convert_coordinates(coordinate_fields(answer.profile)?, to)?;
+        }
+
+        Ok(row)
+    }
+
+    /// Every column a row can have whichever profile of the chain answers it and however
+    /// its checks turn out, empty
+    fn template(&self, chain: &Chain) -> Row {
+        let mut template = Row::new();
+        template.push("行号", json!(null));
+        self.columns.push(&mut template, &csv::StringRecord::new());
+        for &i in self.keep_columns.iter() {
+            template.push(&self.headers[i], json!(null));
+        }
+        let mut names = chain
+            .links
+            .iter()
+            .flat_map(|(_, profile)| profile.res.result_names())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            template.push(name, json!(null));
+        }
+        if self.is_chain {
+            template.push("服务商", json!(null));
+            template.push("accepted", json!(null));
+        }
+        if let QueryColumns::Address {
+            gazetteer: Some(_), ..
+        } = self.columns
+        {
+            for (column, _) in DIVISION_COLUMNS {
+                template.push(column, json!(null));
+            }
+            template.push("区划校验", json!(null));
+            template.push("quality", json!(null));
+        }
+        if self.regions.is_some() {
+            template.push("in_region", json!(null));
+            template.push("distance_to_region_m", json!(null));
+        }
+        template
+    }
+}
+
+/// Run `attempt` until it succeeds, fails for good or runs out of retries. Returns the
+/// number of attempts with the last result.
+async fn with_retries<T, F, Fut>(retries: u32, mut attempt: F) -> (usize, Result<T>)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        let result = attempt().await;
+        attempts += 1;
+
+        match result {
+            Err(e) if attempts <= retries && RowError::is_retryable(&e) => {
+                tokio::time::sleep(Duration::from_millis(500 * attempts as u64)).await;
+            }
+            result => return (attempts as usize, result),
+        }
+    }
+}
+
+/// The number of rows `query_all` sends per request, `None` when rows go one by one
+fn batch_size(chain: &Chain, is_chain: bool, columns: &QueryColumns) -> Result<Option<usize>> {
+    let Some(batch) = chain.first().batch.as_ref() else {
+        return Ok(None);
+    };
+    if is_chain || chain.links.iter().skip(1).any(|(_, p)| p.batch.is_some()) {
+        return Err(anyhow::anyhow!("chains can't use batch endpoints"));
+    }
+    if let QueryColumns::Location { .. } = columns {
+        return Err(anyhow::anyhow!("batch endpoints only geocode addresses"));
+    }
+    Ok(Some(batch.size))
+}
+
+/// Query a batch of rows with one request of the chain's only profile. When the request
+/// fails every row fails with it, otherwise each row gets its own result.
+fn spawn_batch(
+    set: &mut JoinSet<Vec<RowOutcome>>,
+    chain: &Chain,
+    builder: &Arc<RowBuilder>,
+    mut args: ExtraArgs,
+    rows: Vec<(usize, String, csv::StringRecord)>,
+    retries: u32,
+) {
+    let (first, last) = (rows[0].0, rows[rows.len() - 1].0);
+    args.row_id = Some(format!("{}-{}", first, last));
+    let (chain, builder) = (chain.clone(), builder.clone());
+
+    set.spawn(async move {
+        let (name, profile) = &chain.links[0];
+        let addresses = rows
+            .iter()
+            .map(|(_, _, record)| builder.columns.address(record))
+            .collect::<Vec<_>>();
+
+        let query_batch = || profile.query_batch(args.clone(), &addresses);
+        let (attempts, result) = with_retries(retries, query_batch).await;
+        let results = match result {
+            Result::Ok(results) => results,
+            Err(e) => rows.iter().map(|_| Err(share_error(&e))).collect(),
+        };
+
+        rows.into_iter()
+            .zip(results)
+            .map(|((row_number, query, record), result)| {
+                let row = result.and_then(|results| {
+                    let answer = ChainAnswer {
+                        name,
+                        profile,
+                        results,
+                        accepted: true,
+                    };
+                    builder.build(row_number, &query, &record, &answer)
+                });
+                RowOutcome {
+                    row_number,
+                    query,
+                    record,
+                    attempts,
+                    row,
+                }
+            })
+            .collect()
+    });
+}
+
+/// A copy of an error for each row of a failed batch, keeping its kind
+fn share_error(e: &anyhow::Error) -> anyhow::Error {
+    match e.downcast_ref::<ApiError>() {
+        Some(api_error) => api_error.clone().into(),
+        None => RowError {
+            message: format!("{:#}", e),
+            ..RowError::from_error(e)
+        }
+        .into(),
+    }
+}
+
+/// What a `query_all` task hands back once a row is done
+struct RowOutcome {
+    row_number: usize,
+    /// The address, or coordinate when reverse geocoding
+    query: String,
+    record: csv::StringRecord,
+    /// How many times the row was tried
+    attempts: usize,
+    row: Result<Row>,
+}
+
+/// Where `query_all` sends rows which need a closer look
+struct Review {
+    sink: Box<dyn OutputSink>,
+    /// Every column a review row can have, so rows line up whichever comes first
+    template: Row,
+}
+
+impl Review {
+    fn wants(row: &Row) -> bool {
+        row.get("quality").and_then(|q| q.as_str()) == Some(Quality::Low.as_str())
+    }
+
+    fn write(&mut self, row: &Row) -> Result<()> {
+        let mut aligned = self.template.clone();
+        for (k, v) in row.iter() {
+            aligned.push(k, v.clone());
+        }
+        self.sink.write_row(&aligned)
+    }
+}
+
+/// Write a completed row to the output, or to the review file when its quality is low
+fn route(
+    sink: &mut Output,
+    review: Option<&mut Review>,
+    row_number: usize,
+    row: Row,
 ) -> Result<()> {
-    let mut record = csv::StringRecord::new();
+    match review {
+        Some(review) if Review::wants(&row) => {
+            review.write(&row)?;
+            sink.skip(row_number)
+        }
+        _ => sink.write_row(row_number, row),
+    }
+}
 
-    record.push_field(address);
+/// The input columns `query_all` queries a row by
+#[derive(Debug, Clone, Copy)]
+enum QueryColumns {
+    /// Geocode the address in a column, with its parent region in another
+    Address {
+        address: usize,
+        parent: Option<usize>,
+        gazetteer: Option<&'static Gazetteer>,
+    },
+    /// Reverse geocode the coordinate in these columns, given in `system`
+    Location {
+        lng: usize,
+        lat: usize,
+        system: Option<CoordSystem>,
+    },
+}
 
-    for v in obj.values() {
-        if let Some(v) = v.as_str() {
-            record.push_field(v);
-        } else if let Some(_) = v.as_null() {
-            record.push_field("");
-        } else {
-            record.push_field(v.to_string().as_str());
+impl QueryColumns {
+    /// The text identifying the row's query in checkpoints and error messages
+    fn text(&self, record: &csv::StringRecord) -> String {
+        let field = |i: usize| record.get(i).unwrap_or_default().trim();
+        match *self {
+            Self::Address { address, .. } => field(address).to_owned(),
+            Self::Location { lng, lat, .. } => format!("{},{}", field(lng), field(lat)),
         }
     }
 
-    wtr.write_record(record.as_byte_record())?;
+    fn parent<'r>(&self, record: &'r csv::StringRecord) -> Option<&'r str> {
+        let Self::Address {
+            parent: Some(parent),
+            ..
+        } = *self
+        else {
+            return None;
+        };
+        Some(record.get(parent).unwrap_or_default().trim()).filter(|p| !p.is_empty())
+    }
+
+    /// The division of the address with the gazetteer it was looked up in, when
+    /// `--gazetteer` is set. The division is `None` when the name is unknown or ambiguous.
+    fn division(
+        &self,
+        record: &csv::StringRecord,
+    ) -> Option<(&'static Gazetteer, Option<&'static Division>)> {
+        let Self::Address {
+            gazetteer: Some(gazetteer),
+            ..
+        } = *self
+        else {
+            return None;
+        };
+        let division = gazetteer.resolve(&self.text(record), self.parent(record));
+        Some((gazetteer, division))
+    }
 
+    /// The address and parent region of a row, as a batch request takes them
+    fn address(&self, record: &csv::StringRecord) -> (String, Option<String>) {
+        (self.text(record), self.expanded_parent(record))
+    }
+
+    /// The parent region filled into `{parent}`: the regions above the address when
+    /// the gazetteer knows it, or else the parent column
+    fn expanded_parent(&self, record: &csv::StringRecord) -> Option<String> {
+        match self.division(record) {
+            Some((gazetteer, Some(division))) => {
+                let ancestors = gazetteer.ancestors(division);
+                Some(
+                    ancestors
+                        .iter()
+                        .map(|a| a.name.as_str())
+                        .collect::<String>(),
+                )
+                .filter(|parent| !parent.is_empty())
+            }
+            _ => self.parent(record).map(String::from),
+        }
+    }
+
+    fn coordinate(&self, record: &csv::StringRecord) -> Result<(f64, f64, Option<CoordSystem>)> {
+        let Self::Location { lng, lat, system } = *self else {
+            unreachable!("only reverse geocoding has a coordinate")
+        };
+        let parse = |i: usize| {
+            let field = record.get(i).unwrap_or_default().trim();
+            field
+                .parse::<f64>()
+                .with_context(|| format!("invalid coordinate {}", self.text(record)))
+        };
+        Ok((parse(lng)?, parse(lat)?, system))
+    }
+
+    async fn query<'a>(
+        &self,
+        chain: &'a Chain,
+        args: ExtraArgs,
+        record: &csv::StringRecord,
+    ) -> Result<ChainAnswer<'a>> {
+        match self {
+            Self::Address { .. } => {
+                let parent = self.expanded_parent(record);
+                chain
+                    .query_with_address(args, &self.text(record), parent.as_deref())
+                    .await
+            }
+            Self::Location { .. } => {
+                let (lng, lat, system) = self.coordinate(record)?;
+                chain.query_with_location(args, lng, lat, system).await
+            }
+        }
+    }
+
+    fn resolve(
+        &self,
+        profile: &GeoCodingProfile,
+        args: ExtraArgs,
+        record: &csv::StringRecord,
+    ) -> Result<ResolvedRequest> {
+        match self {
+            Self::Address { .. } => {
+                let parent = self.expanded_parent(record);
+                profile.resolve_with_address(args, &self.text(record), parent.as_deref())
+            }
+            Self::Location { .. } => {
+                let (lng, lat, system) = self.coordinate(record)?;
+                profile.resolve_with_location(args, lng, lat, system)
+            }
+        }
+    }
+
+    /// Push the queried input fields to an output row
+    fn push(&self, row: &mut Row, record: &csv::StringRecord) {
+        match *self {
+            Self::Address { .. } => row.push("地址", self.text(record)),
+            Self::Location { lng, lat, .. } => {
+                row.push("经度", record.get(lng).unwrap_or_default());
+                row.push("纬度", record.get(lat).unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// The output columns of the divisions `--gazetteer` adds
+const DIVISION_COLUMNS: [(&str, DivisionLevel); 3] = [
+    ("省份", DivisionLevel::Province),
+    ("城市", DivisionLevel::City),
+    ("区县", DivisionLevel::County),
+];
+
+/// Add the province, city and county of a row, taken from the returned adcode when the
+/// gazetteer knows it and from the input address otherwise. A returned adcode outside
+/// the input's division marks the row's quality as low.
+fn push_divisions(
+    row: &mut Row,
+    gazetteer: &Gazetteer,
+    input: Option<&Division>,
+    adcode: Option<&serde_json::Value>,
+) {
+    let found = adcode.and_then(|adcode| gazetteer.get_adcode(adcode));
+    let hierarchy = found
+        .or(input)
+        .map(|division| gazetteer.hierarchy(division))
+        .unwrap_or_default();
+    for (column, level) in DIVISION_COLUMNS {
+        let division = hierarchy.iter().find(|division| division.level == level);
+        row.push(column, division.map(|division| division.name.as_str()));
+    }
+
+    let check = match (input, found) {
+        (Some(input), Some(found)) => {
+            let within = gazetteer.contains(input.code, found.code)
+                || gazetteer.contains(found.code, input.code);
+            Some(if within { "ok" } else { "mismatch" })
+        }
+        _ => None,
+    };
+    row.push("区划校验", check);
+    if check == Some("mismatch") {
+        row.push("quality", Quality::Low.as_str());
+    }
+}
+
+/// Add whether the row's point lies in its region and how far outside it is. The columns
+/// are empty when there is no region or no point. With `reject`, a point outside its
+/// region fails the row.
+fn check_region(
+    row: &mut Row,
+    region: Option<&Region>,
+    fields: &CoordinateFields,
+    to: CoordSystem,
+    reject: bool,
+) -> Result<()> {
+    let point = row.get_f64(&fields.lng).zip(row.get_f64(&fields.lat));
+    let point = point.map(|(lng, lat)| match fields.system {
+        Some(from) => from.convert(lng, lat, to),
+        None => (lng, lat),
+    });
+
+    let (in_region, distance) = match (region, point) {
+        (Some(region), Some((lng, lat))) => {
+            let distance_m = region.distance_m(lng, lat);
+            if distance_m > 0.0 && reject {
+                let region = region.name.clone();
+                return Err(OutOfRegion {
+                    region,
+                    lng,
+                    lat,
+                    distance_m,
+                }
+                .into());
+            }
+            (Some(distance_m == 0.0), Some(distance_m.round()))
+        }
+        _ => (None, None),
+    };
+    row.push("in_region", in_region);
+    row.push("distance_to_region_m", distance);
     Ok(())
 }
+
+/// Where `query_all` writes rows, either as they complete or in input order
+enum Output {
+    Unordered(Box<dyn OutputSink>),
+    Ordered(OrderedSink),
+}
+
+impl Output {
+    fn write_row(&mut self, row_number: usize, row: Row) -> Result<()> {
+        match self {
+            Self::Unordered(sink) => sink.write_row(&row),
+            Self::Ordered(sink) => sink.write_at(row_number, row),
+        }
+    }
+
+    fn skip(&mut self, row_number: usize) -> Result<()> {
+        match self {
+            Self::Unordered(_) => Ok(()),
+            Self::Ordered(sink) => sink.skip(row_number),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Unordered(sink) => sink.finish(),
+            Self::Ordered(sink) => sink.finish(),
+        }
+    }
+}
+
+async fn check(args: CheckArgs) -> Result<bool> {
+    let config_file = args.config.unwrap_or_else(|| "./poi.yaml".to_string());
+    let config = GeoCodingConfig::load_yaml(&config_file).await?;
+
+    let mut names = match args.profile {
+        Some(name) => {
+            config.get_profile(&name).ok_or_else(|| {
+                anyhow::anyhow!("Profile {} not found in config file {}", name, config_file)
+            })?;
+            vec![name]
+        }
+        None => config
+            .profiles
+            .iter()
+            .filter(|(_, profile)| profile.check.is_some())
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+    names.sort();
+
+    if names.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No profile with a check section found in config file {}",
+            config_file
+        ));
+    }
+
+    let extra_args = ExtraArgs::from(args.extra_params);
+    let (pass, fail) = (Style::new().green(), Style::new().red());
+    let mut all_passed = true;
+
+    for name in names {
+        let profile = config.get_profile(&name).unwrap();
+        let assertions = profile
+            .check(&extra_args)
+            .await
+            .with_context(|| format!("failed to check profile {}", name))?;
+
+        println!("{}", name);
+        for assertion in assertions {
+            all_passed &= assertion.passed;
+            if assertion.passed {
+                println!("  {} {}", pass.apply_to("✓"), assertion.name);
+            } else {
+                println!(
+                    "  {} {}: {}",
+                    fail.apply_to("✗"),
+                    assertion.name,
+                    assertion.detail
+                );
+            }
+        }
+    }
+
+    Ok(all_passed)
+}
+
+async fn diff(args: DiffArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "./poi.yaml".to_string());
+    let config = GeoCodingConfig::load_yaml(&config_file).await?;
+
+    let get_profile = |name: &str| {
+        config.get_profile(name).ok_or_else(|| {
+            anyhow::anyhow!("Profile {} not found in config file {}", name, config_file)
+        })
+    };
+    let profile1 = get_profile(&args.profile1)?;
+    let profile2 = get_profile(&args.profile2)?;
+
+    let extra_args = ExtraArgs::from(args.extra_params);
+    // each profile takes the address in its own param
+    let args_of = |profile: &GeoCodingProfile| {
+        let mut extra_args = extra_args.clone();
+        if let Some(address) = &args.address {
+            let param = profile.address.clone().unwrap_or_default().param;
+            extra_args.query.push((param, address.clone()));
+        }
+        extra_args
+    };
+    let (args1, args2) = (args_of(profile1), args_of(profile2));
+
+    let (body1, body2) = tokio::try_join!(profile1.query_raw(&args1), profile2.query_raw(&args2))?;
+
+    // the providers answer in different systems, compare the points in wgs84
+    let system_of = |profile: &GeoCodingProfile| profile.res.coordinates.as_ref()?.system;
+    let coords = |profile: &GeoCodingProfile, body: &serde_json::Value| {
+        let get_f64 = |path: &str| {
+            let v = get!(body.clone(), json!(path));
+            v.as_f64()
+                .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+        };
+        let (lng, lat) = get_f64(&args.lng_path).zip(get_f64(&args.lat_path))?;
+        Some(match system_of(profile) {
+            Some(system) => system.convert(lng, lat, CoordSystem::Wgs84),
+            None => (lng, lat),
+        })
+    };
+    let distance = match (coords(profile1, &body1), coords(profile2, &body2)) {
+        (Some((lng1, lat1)), Some((lng2, lat2))) => {
+            Some(distance_in_metres(lng1, lat1, lng2, lat2))
+        }
+        _ => None,
+    };
+    let unconverted = [(&args.profile1, profile1), (&args.profile2, profile2)]
+        .into_iter()
+        .filter(|(_, profile)| system_of(profile).is_none())
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+
+    let (left, right) = if args.full {
+        let (mut body1, mut body2) = (body1, body2);
+        profile1.res.strip(&mut body1);
+        profile2.res.strip(&mut body2);
+        (body1, body2)
+    } else {
+        (profile1.res.pick(&body1), profile2.res.pick(&body2))
+    };
+
+    let mut output = String::new();
+    let (removed, added) = (Style::new().red(), Style::new().green());
+    writeln!(
+        output,
+        "{}",
+        removed.apply_to(format!("--- {}", args.profile1))
+    )?;
+    writeln!(
+        output,
+        "{}",
+        added.apply_to(format!("+++ {}", args.profile2))
+    )?;
+
+    let entries = diff_json(&left, &right);
+    for entry in &entries {
+        match entry {
+            DiffEntry::Removed(path, v) => {
+                writeln!(output, "{}", removed.apply_to(format!("- {}: {}", path, v)))?;
+            }
+            DiffEntry::Added(path, v) => {
+                writeln!(output, "{}", added.apply_to(format!("+ {}: {}", path, v)))?;
+            }
+            DiffEntry::Changed(path, l, r) => {
+                writeln!(output, "{}", removed.apply_to(format!("- {}: {}", path, l)))?;
+                writeln!(output, "{}", added.apply_to(format!("+ {}: {}", path, r)))?;
+            }
+        }
+    }
+    if entries.is_empty() {
+        writeln!(output, "no difference")?;
+    }
+
+    match distance {
+        Some(distance) if unconverted.is_empty() => {
+            writeln!(output, "distance: {:.1} m", distance)?
+        }
+        Some(distance) => writeln!(
+            output,
+            "distance: {:.1} m (taken as wgs84, {} set no `res.coordinates.system`)",
+            distance,
+            unconverted.join(" and ")
+        )?,
+        None => writeln!(output, "distance: n/a (coordinates not found)")?,
+    }
+
+    let mut stdout = stdout().lock();
+    write!(stdout, "{}", output)?;
+
+    Ok(())
+}
+
+fn cache(args: CacheArgs) -> Result<()> {
+    let cache = Cache::new(&args.cache_dir).with_ttl(args.cache_ttl);
+
+    match args.action {
+        CacheAction::Stats => {
+            let stats = cache.stats()?;
+            println!("dir: {}", cache.dir().display());
+            println!("entries: {}", stats.entries);
+            println!("expired: {}", stats.expired);
+            println!("bytes: {}", stats.bytes);
+        }
+        CacheAction::Purge { expired } => {
+            let removed = cache.purge(expired)?;
+            println!("removed {} entries", removed);
+        }
+        CacheAction::Export { output } => {
+            let mut wtr = open_output(output.as_deref())?;
+            for entry in cache.entries()? {
+                serde_json::to_writer(&mut wtr, &entry)?;
+                writeln!(wtr)?;
+            }
+            wtr.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+impl SendOpts {
+    /// Whether requests are only printed, not sent
+    fn is_dry_run(&self) -> bool {
+        self.dry_run || self.print_curl
+    }
+
+    fn print_request(&self, req: &ResolvedRequest) {
+        if self.print_curl {
+            println!("{}\n", req.to_curl());
+        } else {
+            println!("{}", req);
+        }
+    }
+
+    fn apply(&self, config: GeoCodingConfig) -> Result<GeoCodingConfig> {
+        let cache = (!self.no_cache).then(|| {
+            let cache = Cache::new(&self.cache_dir)
+                .with_ttl(self.cache_ttl)
+                .with_refresh(self.refresh);
+            Arc::new(cache)
+        });
+        let recorder = self
+            .record
+            .as_deref()
+            .map(Recorder::open)
+            .transpose()?
+            .map(Arc::new);
+
+        let replay = self
+            .replay
+            .as_deref()
+            .map(Replay::open)
+            .transpose()?
+            .map(Arc::new);
+
+        Ok(config
+            .with_cache(cache)
+            .with_recorder(recorder)
+            .with_replay(replay))
+    }
+}
+
+/// Open the output file, or stdout when no path is given
+fn open_output(path: Option<&str>) -> Result<Box<dyn std::io::Write + Send>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(stdout()),
+    })
+}
+
+/// The coordinate fields of a profile, when it says which system they are in
+fn coordinate_fields(profile: &GeoCodingProfile) -> Result<&CoordinateFields> {
+    profile
+        .res
+        .coordinates
+        .as_ref()
+        .filter(|fields| fields.system.is_some())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "the profile must set `res.coordinates` and its `system` to convert coordinates"
+            )
+        })
+}
+
+fn picked_results<'a>(
+    address: &str,
+    result: &'a serde_json::Value,
+) -> Result<&'a serde_json::Map<String, serde_json::Value>> {
+    result
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("picked results of {} are not an object", address))
+}
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result};
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method, Response, Url,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Tells apart the temp files of concurrent `Cache::put`s of the same key
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Query params left out of cache keys and logs, they are credentials rather than part of the question.
+pub const SECRET_PARAMS: &[&str] = &[
+    "ak",
+    "sk",
+    "sn",
+    "key",
+    "token",
+    "access_token",
+    "api_key",
+    "apikey",
+];
+
+/// An on-disk cache of successful responses, one JSON file per request.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    refresh: bool,
+}
+
+/// A response as stored in the cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub key: String,
+    /// Seconds since the unix epoch
+    pub created_at: u64,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+}
+
+pub fn is_secret_param(name: &str) -> bool {
+    SECRET_PARAMS.contains(&name.to_lowercase().as_str())
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: None,
+            refresh: false,
+        }
+    }
+
+    /// Entries older than `ttl` are ignored and overwritten
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Ignore existing entries but still store new responses
+    pub fn with_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The normalized request a response is cached under.
+    ///
+    /// Params, those of the url included, are sorted and secret params are left out, so
+    /// rotating keys doesn't invalidate the cache.
+    pub fn key(method: &Method, url: &Url, params: &serde_json::Value, body: &str) -> String {
+        let mut pairs = url
+            .query_pairs()
+            .filter(|(k, _)| !is_secret_param(k))
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>();
+        let mut url = url.clone();
+        url.set_query(None);
+
+        if let Some(params) = params.as_object() {
+            pairs.extend(params.iter().filter(|(k, _)| !is_secret_param(k)).map(
+                |(k, v)| match v {
+                    serde_json::Value::String(s) => format!("{}={}", k, s),
+                    v => format!("{}={}", k, v),
+                },
+            ));
+        }
+        pairs.sort();
+
+        format!("{} {}?{} {}", method, url, pairs.join("&"), body)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        if self.refresh {
+            return Ok(None);
+        }
+
+        let path = self.path(key);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // a corrupt entry is just a miss, it gets overwritten
+        let entry = match serde_json::from_str::<CachedResponse>(&content) {
+            Ok(entry) if entry.key == key && !self.is_expired(&entry) => entry,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(entry))
+    }
+
+    pub fn put(&self, entry: &CachedResponse) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create cache dir {}", self.dir.display()))?;
+
+        // write to a temp file first, so a crash never leaves a half written entry,
+        // unique per put so rows with the same address don't race on one temp file
+        let path = self.path(&entry.key);
+        let tmp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp, serde_json::to_vec(entry)?)?;
+        if let Err(e) = fs::rename(&tmp, path) {
+            let _ = fs::remove_file(tmp);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Every entry in the cache, expired ones included
+    pub fn entries(&self) -> Result<Vec<CachedResponse>> {
+        let mut entries = self
+            .files()?
+            .into_iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .filter_map(|content| serde_json::from_str::<CachedResponse>(&content).ok())
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(entries)
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for path in self.files()? {
+            stats.entries += 1;
+            stats.bytes += fs::metadata(&path)?.len();
+
+            let expired = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<CachedResponse>(&content).ok())
+                .is_none_or(|entry| self.is_expired(&entry));
+            if expired {
+                stats.expired += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Remove entries, only the expired (or unreadable) ones if `expired_only` is set.
+    /// Returns the number of entries removed.
+    pub fn purge(&self, expired_only: bool) -> Result<usize> {
+        let mut removed = 0;
+        for path in self.files()? {
+            if expired_only {
+                let entry = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<CachedResponse>(&content).ok());
+                if entry.is_some_and(|entry| !self.is_expired(&entry)) {
+                    continue;
+                }
+            }
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        let name = hash
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect())
+    }
+
+    fn is_expired(&self, entry: &CachedResponse) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now().saturating_sub(entry.created_at) > ttl.as_secs())
+    }
+}
+
+impl CachedResponse {
+    pub fn new(key: String, status: u16, headers: Vec<(String, String)>, body: String) -> Self {
+        Self {
+            key,
+            created_at: now(),
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// Rebuild a response as if it came from the network
+    pub fn to_response(&self) -> Result<Response> {
+        build_response(
+            self.status,
+            self.headers.iter().map(|(k, v)| (k, v)),
+            &self.body,
+        )
+    }
+}
+
+/// A response built from stored parts, shared by the cache, the request log and its replay
+pub(crate) fn build_response<K, V>(
+    status: u16,
+    headers: impl IntoIterator<Item = (K, V)>,
+    body: &str,
+) -> Result<Response>
+where
+    HeaderName: TryFrom<K>,
+    <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+    HeaderValue: TryFrom<V>,
+    <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+{
+    let mut builder = http::Response::builder().status(status);
+    for (k, v) in headers {
+        builder = builder.header(k, v);
+    }
+
+    Ok(builder.body(body.to_string())?.into())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn key_should_ignore_param_order_and_secrets() {
+        let url = Url::parse("https://api.map.baidu.com/geocoding/v3/?foo=bar").unwrap();
+
+        let a = Cache::key(
+            &Method::GET,
+            &url,
+            &json!({"address": "白银市", "ak": "secret1", "output": "json"}),
+            "{}",
+        );
+        let b = Cache::key(
+            &Method::GET,
+            &url,
+            &json!({"output": "json", "ak": "secret2", "address": "白银市"}),
+            "{}",
+        );
+
+        assert_eq!(a, b);
+        assert_eq!(
+            a,
+            "GET https://api.map.baidu.com/geocoding/v3/?address=白银市&foo=bar&output=json {}"
+        );
+
+        // the url's own query tells endpoints apart, but not its secrets
+        let key = |url| Cache::key(&Method::GET, &Url::parse(url).unwrap(), &json!({}), "{}");
+        assert_ne!(
+            key("https://example.com/geo?output=json"),
+            key("https://example.com/geo?output=xml")
+        );
+        assert_eq!(
+            key("https://example.com/geo?output=json&ak=secret1"),
+            key("https://example.com/geo?ak=secret2&output=json")
+        );
+    }
+
+    #[tokio::test]
+    async fn put_and_get_should_work() {
+        let dir = std::env::temp_dir().join(format!("poi_cache_{}", std::process::id()));
+        let cache = Cache::new(&dir);
+
+        let entry = CachedResponse::new(
+            "GET https://example.com/? {}".into(),
+            200,
+            vec![("content-type".into(), "application/json".into())],
+            r#"{"status":0}"#.into(),
+        );
+        cache.put(&entry).unwrap();
+
+        assert_eq!(cache.get(&entry.key).unwrap(), Some(entry.clone()));
+        assert_eq!(cache.get("GET https://example.com/?a=1 {}").unwrap(), None);
+        assert_eq!(
+            cache.clone().with_refresh(true).get(&entry.key).unwrap(),
+            None
+        );
+        assert_eq!(cache.stats().unwrap().entries, 1);
+
+        let res = entry.to_response().unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), r#"{"status":0}"#);
+
+        assert_eq!(cache.purge(false).unwrap(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_puts_of_one_key_should_work() {
+        let dir = std::env::temp_dir().join(format!("poi_cache_race_{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let entry = CachedResponse::new(
+            "GET https://example.com/? {}".into(),
+            200,
+            vec![],
+            "{}".into(),
+        );
+
+        std::thread::scope(|s| {
+            let handles = (0..8)
+                .map(|_| s.spawn(|| cache.put(&entry)))
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().unwrap().unwrap();
+            }
+        });
+
+        assert_eq!(cache.get(&entry.key).unwrap(), Some(entry));
+        assert_eq!(cache.stats().unwrap().entries, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::Row;
+
+/// Records completed rows of a batch run, one JSON line per row, so a restarted
+/// run can skip them.
+pub struct Checkpoint {
+    file: File,
+    done: HashMap<String, Row>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    row: Row,
+}
+
+impl Checkpoint {
+    /// Open or create a checkpoint file and load the rows already completed.
+    ///
+    /// A trailing line left incomplete by a crash is dropped, that row is simply queried again.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open checkpoint {}", path.display()))?;
+
+        let mut done = HashMap::new();
+        let mut valid_len = 0;
+        let mut reader = BufReader::new(&mut file);
+        let mut line = String::new();
+
+        while reader.read_line(&mut line)? > 0 {
+            if !line.ends_with('\n') {
+                break;
+            }
+            match serde_json::from_str::<Entry>(&line) {
+                Ok(entry) => {
+                    done.insert(entry.key, entry.row);
+                }
+                Err(_) => break,
+            }
+            valid_len += line.len() as u64;
+            line.clear();
+        }
+
+        if valid_len < file.seek(SeekFrom::End(0))? {
+            file.set_len(valid_len)?;
+        }
+
+        Ok(Self { file, done })
+    }
+
+    /// The key identifying an input row across runs
+    pub fn key(row_number: usize, address: &str) -> String {
+        format!("{}:{}", row_number, address)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Row> {
+        self.done.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    /// Append a completed row, the line is flushed before returning
+    pub fn record(&mut self, key: &str, row: &Row) -> Result<()> {
+        let entry = Entry {
+            key: key.to_string(),
+            row: row.clone(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.done.insert(entry.key, entry.row);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checkpoint_should_survive_truncated_write() {
+        let path =
+            std::env::temp_dir().join(format!("poi_checkpoint_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut row = Row::new();
+        row.push("地址", "白银市");
+        row.push("纬度坐标", 36.54);
+
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        checkpoint
+            .record(&Checkpoint::key(1, "白银市"), &row)
+            .unwrap();
+        drop(checkpoint);
+
+        // simulate a crash in the middle of writing the second row
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all("{\"key\":\"2:定西".as_bytes()).unwrap();
+        drop(file);
+
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.len(), 1);
+        assert_eq!(checkpoint.get("1:白银市"), Some(&row));
+        assert_eq!(checkpoint.get("2:定西市"), None);
+
+        checkpoint
+            .record(&Checkpoint::key(2, "定西市"), &row)
+            .unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
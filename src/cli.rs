@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use clap::{Parser, Subcommand};
+use std::time::Duration;
 
 use crate::ExtraArgs;
 
@@ -80,6 +81,26 @@ pub fn parse_key_value(s: &str) -> Result<KeyVal> {
     })
 }
 
+/// Parse a duration like `3600`, `90s`, `30m`, `12h` or `7d`, plain numbers are seconds
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (n, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+
+    let n: u64 = n.parse().map_err(|_| anyhow!("Invalid duration {}", s))?;
+    let secs = match unit {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 60 * 60,
+        "d" => n * 60 * 60 * 24,
+        _ => return Err(anyhow!("Invalid duration {}", s)),
+    };
+
+    Ok(Duration::from_secs(secs))
+}
+
 impl From<Vec<KeyVal>> for ExtraArgs {
     fn from(args: Vec<KeyVal>) -> Self {
         let mut headers = vec![];
@@ -98,6 +119,7 @@ impl From<Vec<KeyVal>> for ExtraArgs {
             headers,
             query,
             body,
+            ..Default::default()
         }
     }
 }
@@ -136,17 +158,27 @@ mod test {
                 headers: vec![("Content-Type".to_string(), "application/json".to_string())],
                 query: vec![("id".to_string(), "1".to_string())],
                 body: vec![("name".to_string(), "misky".to_string())],
+                row_id: None,
             }
         )
     }
 
+    #[test]
+    fn parse_duration_should_work() {
+        assert_eq!(parse_duration("3600").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(604800));
+        assert!(parse_duration("7w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
     #[test]
     fn parse_key_val_should_work() {
         let args = vec!["%Content-Type=application/json", "id=1", "@name=misky"];
 
         let key_vals = args
             .into_iter()
-            .map(|s| parse_key_value(s))
+            .map(parse_key_value)
             .collect::<Result<Vec<_>>>()
             .unwrap();
 
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+/// Describes how an address is sent to a geocoding provider, and how to reformulate it
+/// when the provider finds nothing, e.g. `{address}人民政府`, then `{address}`, then
+/// `{parent}{address}`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AddressProfile {
+    /// The query param the address is sent in
+    #[serde(default = "default_param")]
+    pub param: String,
+
+    /// The formulations to try in order. `{address}` is the input address and
+    /// `{parent}` its parent region, templates using a missing field are skipped.
+    #[serde(default = "default_templates")]
+    pub templates: Vec<String>,
+}
+
+fn default_param() -> String {
+    "address".into()
+}
+
+fn default_templates() -> Vec<String> {
+    vec!["{address}人民政府".into()]
+}
+
+impl Default for AddressProfile {
+    fn default() -> Self {
+        Self {
+            param: default_param(),
+            templates: default_templates(),
+        }
+    }
+}
+
+impl AddressProfile {
+    /// The formulations of an address, in the order they are tried
+    pub fn formulations(&self, address: &str, parent: Option<&str>) -> Vec<String> {
+        let mut formulations: Vec<String> = vec![];
+        for template in &self.templates {
+            let formulation = match parent {
+                Some(parent) => template.replace("{parent}", parent),
+                None if template.contains("{parent}") => continue,
+                None => template.clone(),
+            };
+            let formulation = formulation.replace("{address}", address);
+            if !formulations.contains(&formulation) {
+                formulations.push(formulation);
+            }
+        }
+        formulations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formulations_should_work() {
+        let address = AddressProfile::default();
+        assert_eq!(address.param, "address");
+        assert_eq!(address.formulations("白银市", None), ["白银市人民政府"]);
+
+        let address: AddressProfile = serde_yaml::from_str(
+            r#"
+            param: keywords
+            templates: ['{address}人民政府', '{address}', '{parent}{address}']
+            "#,
+        )
+        .unwrap();
+        assert_eq!(address.param, "keywords");
+        assert_eq!(
+            address.formulations("城关镇", Some("甘肃省")),
+            ["城关镇人民政府", "城关镇", "甘肃省城关镇"]
+        );
+        assert_eq!(
+            address.formulations("城关镇", None),
+            ["城关镇人民政府", "城关镇"]
+        );
+        // an empty parent gives the same formulation twice
+        assert_eq!(
+            address.formulations("城关镇", Some("")),
+            ["城关镇人民政府", "城关镇"]
+        );
+    }
+}
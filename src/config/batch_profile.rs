@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::split_results;
+use crate::cell_text;
+
+/// Sends several addresses in one request, e.g. AMap's `batch=true` with `|`-joined
+/// addresses, and splits the results back out by position.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BatchProfile {
+    /// How many addresses go in one request
+    #[serde(default = "default_size")]
+    pub size: usize,
+
+    /// What joins the addresses in the address param
+    #[serde(default = "default_separator")]
+    pub separator: String,
+
+    /// Query params only batch requests send, e.g. `batch: true`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub params: BTreeMap<String, Value>,
+
+    /// Path of the array holding one result per address, e.g. `geocodes`
+    pub results: String,
+}
+
+fn default_size() -> usize {
+    10
+}
+
+fn default_separator() -> String {
+    "|".into()
+}
+
+impl BatchProfile {
+    pub fn validate(&self) -> Result<()> {
+        if self.size == 0 {
+            return Err(anyhow!("batch size must be at least 1"));
+        }
+        if self.separator.is_empty() {
+            return Err(anyhow!("batch separator must not be empty"));
+        }
+        Ok(())
+    }
+
+    /// The query params of a batch of address formulations
+    pub fn params(&self, param: &str, addresses: &[String]) -> Vec<(String, String)> {
+        let mut params = vec![(param.to_string(), addresses.join(&self.separator))];
+        params.extend(self.params.iter().map(|(k, v)| (k.clone(), cell_text(v))));
+        params
+    }
+
+    /// Split a batch response into one body per address, in the order of the addresses
+    pub fn split(&self, body: &Value, count: usize) -> Result<Vec<Value>> {
+        let bodies = split_results(body, &self.results)?;
+        if bodies.len() != count {
+            return Err(anyhow!(
+                "batch response has {} results for {} addresses",
+                bodies.len(),
+                count
+            ));
+        }
+        Ok(bodies)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn split_should_work() {
+        let batch: BatchProfile = serde_yaml::from_str(
+            r#"
+            size: 2
+            params:
+              batch: true
+            results: geocodes
+            "#,
+        )
+        .unwrap();
+
+        let addresses = ["白银市".to_string(), "定西市".to_string()];
+        assert_eq!(
+            batch.params("address", &addresses),
+            [
+                ("address".to_string(), "白银市|定西市".to_string()),
+                ("batch".to_string(), "true".to_string()),
+            ]
+        );
+
+        let body = json!({"status": "1", "geocodes": [{"adcode": "620400"}, {"adcode": "621100"}]});
+        let bodies = batch.split(&body, 2).unwrap();
+        assert_eq!(
+            bodies[1],
+            json!({"status": "1", "geocodes": [{"adcode": "621100"}]})
+        );
+
+        assert!(batch.split(&body, 3).is_err());
+        assert!(batch.split(&json!({"status": "1"}), 2).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{cell_text, ResponseProfile};
+
+/// Tries several profiles in order until one gives an acceptable answer,
+/// e.g. Baidu first, then AMap and Tencent when Baidu only knows the city.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ChainProfile {
+    /// The names of the profiles to try, in order
+    pub profiles: Vec<String>,
+
+    /// What an answer needs for the chain to stop at it
+    #[serde(skip_serializing_if = "crate::config::is_default", default)]
+    pub accept: Acceptance,
+}
+
+/// Acceptance criteria of a chain. A criterion is skipped for profiles which
+/// don't pick the field it looks at.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Acceptance {
+    /// The lowest confidence accepted on a 0–100 scale, read from the profile's
+    /// `res.confidence` field and scaled by its `res.confidence_scale`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min_confidence: Option<f64>,
+
+    /// The precision levels accepted, read from the profile's `res.level` field
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub levels: Vec<String>,
+}
+
+impl Acceptance {
+    /// Whether the results picked by a profile with the response profile `res` are good enough
+    pub fn accepts(&self, res: &ResponseProfile, results: &serde_json::Value) -> bool {
+        let field = |name: &str| results.get(name).cloned().unwrap_or_default();
+
+        if let (Some(min), Some(_)) = (self.min_confidence, &res.confidence) {
+            if !res
+                .confidence(results)
+                .is_some_and(|confidence| confidence >= min)
+            {
+                return false;
+            }
+        }
+
+        if let (false, Some(name)) = (self.levels.is_empty(), &res.level) {
+            let level = cell_text(&field(name));
+            if !self.levels.contains(&level) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_should_work() {
+        let chain: ChainProfile = serde_yaml::from_str(
+            r#"
+            profiles: [baidu, amap]
+            accept:
+              min_confidence: 50
+              levels: [门址, 兴趣点]
+            "#,
+        )
+        .unwrap();
+
+        let res = ResponseProfile {
+            confidence: Some("可信度".into()),
+            level: Some("地址结构".into()),
+            ..Default::default()
+        };
+
+        let accept = &chain.accept;
+        assert!(accept.accepts(&res, &json!({"可信度": 80, "地址结构": "门址"})));
+        assert!(!accept.accepts(&res, &json!({"可信度": 80, "地址结构": "城市"})));
+        assert!(!accept.accepts(&res, &json!({"可信度": "20", "地址结构": "门址"})));
+        assert!(!accept.accepts(&res, &json!({"地址结构": "门址"})));
+
+        // a profile without a confidence field is only judged by its level
+        let res = ResponseProfile {
+            level: Some("地址结构".into()),
+            ..Default::default()
+        };
+        assert!(accept.accepts(&res, &json!({"地址结构": "兴趣点"})));
+
+        // tencent's reliability goes from 1 to 10
+        let res = ResponseProfile {
+            confidence: Some("可信度".into()),
+            confidence_scale: Some(10.0),
+            ..Default::default()
+        };
+        assert!(accept.accepts(&res, &json!({"可信度": 7})));
+        assert!(!accept.accepts(&res, &json!({"可信度": 4})));
+    }
+}
@@ -0,0 +1,162 @@
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_json_lodash::get;
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// Expectations a profile's response must meet, used by the `check` command
+/// to catch provider API drift before a batch job does.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CheckProfile {
+    /// Expected http status code
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status: Option<u16>,
+
+    /// JSON paths which must exist and not be null
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub exists: Vec<String>,
+
+    /// JSON paths whose numeric value must fall into the given range
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub ranges: HashMap<String, NumberRange>,
+
+    /// Maximum latency of the request in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_latency_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct NumberRange {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max: Option<f64>,
+}
+
+/// The outcome of a single expectation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl CheckProfile {
+    /// Evaluate every expectation against a response, in a stable order.
+    pub fn assert(
+        &self,
+        status: StatusCode,
+        body: &serde_json::Value,
+        latency: Duration,
+    ) -> Vec<Assertion> {
+        let mut assertions = vec![];
+
+        if let Some(expected) = self.status {
+            assertions.push(Assertion::new(
+                "status",
+                status.as_u16() == expected,
+                format!("expected {}, got {}", expected, status.as_u16()),
+            ));
+        }
+
+        for path in &self.exists {
+            let v = get!(body.clone(), json!(path));
+            assertions.push(Assertion::new(
+                format!("exists {}", path),
+                !v.is_null(),
+                format!("got {}", v),
+            ));
+        }
+
+        let mut ranges = self.ranges.iter().collect::<Vec<_>>();
+        ranges.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, range) in ranges {
+            let v = get!(body.clone(), json!(path));
+            let n = v
+                .as_f64()
+                .or_else(|| v.as_str().and_then(|s| s.parse().ok()));
+            assertions.push(Assertion::new(
+                format!("range {}", path),
+                n.is_some_and(|n| range.contains(n)),
+                format!("expected {}, got {}", range, v),
+            ));
+        }
+
+        if let Some(max) = self.max_latency_ms {
+            let latency = latency.as_millis();
+            assertions.push(Assertion::new(
+                "latency",
+                latency <= max as u128,
+                format!("expected <= {}ms, got {}ms", max, latency),
+            ));
+        }
+
+        assertions
+    }
+}
+
+impl NumberRange {
+    pub fn contains(&self, n: f64) -> bool {
+        self.min.is_none_or(|min| n >= min) && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+impl fmt::Display for NumberRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, "{} ~ {}", min, max),
+            (Some(min), None) => write!(f, ">= {}", min),
+            (None, Some(max)) => write!(f, "<= {}", max),
+            (None, None) => write!(f, "any number"),
+        }
+    }
+}
+
+impl Assertion {
+    fn new(name: impl Into<String>, passed: bool, detail: String) -> Self {
+        Self {
+            name: name.into(),
+            passed,
+            detail,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn assert_should_work() {
+        let check: CheckProfile = serde_yaml::from_str(
+            r#"
+            status: 200
+            exists: [result.level]
+            ranges:
+              result.location.lat: { min: 3, max: 54 }
+              result.location.lng: { min: 73, max: 136 }
+            max_latency_ms: 500
+            "#,
+        )
+        .unwrap();
+
+        let body = json!({"result": {"location": {"lat": 40.05, "lng": 200.1}}});
+        let assertions = check.assert(StatusCode::OK, &body, Duration::from_millis(800));
+
+        let failed = assertions
+            .iter()
+            .filter(|a| !a.passed)
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            failed,
+            vec![
+                "exists result.level",
+                "range result.location.lng",
+                "latency"
+            ]
+        );
+    }
+}
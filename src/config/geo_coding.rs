@@ -1,20 +1,33 @@
 use std::collections::HashMap;
 
-use crate::ExtraArgs;
+use crate::{
+    ApiError, ApiErrorKind, Cache, CoordSystem, ExtraArgs, ProfileRecorder, Recorder, Replay,
+    RequestStats,
+};
 use anyhow::Ok;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::{json, Value};
+use std::{future::Future, sync::Arc, time::Instant};
 
+use super::address_profile::AddressProfile;
+use super::batch_profile::BatchProfile;
+use super::chain_profile::{Acceptance, ChainProfile};
+use super::check_profile::{Assertion, CheckProfile};
+use super::location_profile::LocationProfile;
 use super::response_profile::ResponseProfile;
 use super::LoadConfig;
 use super::ValidateConfig;
-use super::{is_default, RequestProfile};
+use super::{is_default, RequestProfile, ResolvedRequest};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "HashMap<String, Value>", into = "HashMap<String, Value>")]
 pub struct GeoCodingConfig {
-    #[serde(flatten)]
     pub profiles: HashMap<String, GeoCodingProfile>,
+
+    /// Entries holding a `chain:` key instead of a request
+    pub chains: HashMap<String, ChainProfile>,
 }
 
 impl LoadConfig for GeoCodingConfig {}
@@ -25,18 +38,126 @@ impl ValidateConfig for GeoCodingConfig {
                 .validate()
                 .context(format!("failed to validate profile: {}", name))?;
         }
+
+        for (name, chain) in &self.chains {
+            if chain.profiles.is_empty() {
+                return Err(anyhow!("chain {} lists no profiles", name));
+            }
+            if let Some(missing) = chain
+                .profiles
+                .iter()
+                .find(|profile| !self.profiles.contains_key(*profile))
+            {
+                return Err(anyhow!("chain {} uses unknown profile {}", name, missing));
+            }
+        }
         Ok(())
     }
 }
 
+impl TryFrom<HashMap<String, Value>> for GeoCodingConfig {
+    type Error = String;
+
+    fn try_from(entries: HashMap<String, Value>) -> Result<Self, Self::Error> {
+        let mut config = Self::new(HashMap::new());
+
+        for (name, entry) in entries {
+            let parsed = match entry.get("chain") {
+                Some(chain) => serde_json::from_value(chain.clone())
+                    .map(|chain| config.chains.insert(name.clone(), chain))
+                    .map(|_| ()),
+                None => serde_json::from_value(entry)
+                    .map(|profile| config.profiles.insert(name.clone(), profile))
+                    .map(|_| ()),
+            };
+            parsed.map_err(|e| format!("failed to parse profile {}: {}", name, e))?;
+        }
+
+        std::result::Result::Ok(config)
+    }
+}
+
+impl From<GeoCodingConfig> for HashMap<String, Value> {
+    fn from(config: GeoCodingConfig) -> Self {
+        let profiles = config
+            .profiles
+            .into_iter()
+            .map(|(name, profile)| (name, serde_json::to_value(profile)));
+        let chains = config.chains.into_iter().map(|(name, chain)| {
+            (
+                name,
+                serde_json::to_value(chain).map(|c| json!({ "chain": c })),
+            )
+        });
+
+        profiles
+            .chain(chains)
+            .map(|(name, value)| (name, value.expect("profiles serialize to json")))
+            .collect()
+    }
+}
+
 impl GeoCodingConfig {
     pub fn new(profiles: HashMap<String, GeoCodingProfile>) -> Self {
-        Self { profiles }
+        Self {
+            profiles,
+            chains: HashMap::new(),
+        }
     }
 
     pub fn get_profile(&self, name: &str) -> Option<&GeoCodingProfile> {
         self.profiles.get(name)
     }
+
+    /// Look up a chain with its profiles. A plain profile is a chain of itself
+    /// accepting any answer.
+    pub fn get_chain(&self, name: &str) -> Option<Chain> {
+        let (names, accept) = match self.chains.get(name) {
+            Some(chain) => (chain.profiles.clone(), chain.accept.clone()),
+            None => (vec![name.to_string()], Acceptance::default()),
+        };
+
+        let links = names
+            .into_iter()
+            .map(|name| Some((name.clone(), self.profiles.get(&name)?.clone())))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Chain { links, accept })
+    }
+
+    /// Log the requests of every profile to `recorder`
+    pub fn with_recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        for (name, profile) in self.profiles.iter_mut() {
+            profile.req.recorder = recorder
+                .clone()
+                .map(|recorder| ProfileRecorder::new(recorder, name.as_str()));
+        }
+        self
+    }
+
+    /// Serve the responses of every profile from a request log
+    pub fn with_replay(mut self, replay: Option<Arc<Replay>>) -> Self {
+        for profile in self.profiles.values_mut() {
+            profile.req.replay = replay.clone();
+        }
+        self
+    }
+
+    /// Serve and store the responses of every profile through `cache`
+    pub fn with_cache(mut self, cache: Option<Arc<Cache>>) -> Self {
+        for profile in self.profiles.values_mut() {
+            profile.req.cache = cache.clone();
+        }
+        self
+    }
+
+    /// Count the requests every profile sends over the network in `stats`
+    pub fn with_stats(mut self, stats: Option<Arc<RequestStats>>) -> Self {
+        for profile in self.profiles.values_mut() {
+            profile.req.stats = stats.clone();
+        }
+        self
+    }
 }
 
 impl GeoCodingProfile {
@@ -45,20 +166,332 @@ impl GeoCodingProfile {
         Ok(res.get_results(&self.res).await?)
     }
 
+    /// Send the request and return the raw response body
+    pub async fn query_raw(&self, args: &ExtraArgs) -> Result<serde_json::Value> {
+        let res = self.req.send(args).await?;
+        res.json().await
+    }
+
     pub async fn query_with_city(&self, args: ExtraArgs, city: &str) -> Result<serde_json::Value> {
-        let mut args = args.clone();
+        self.query_with_address(args, city, None).await
+    }
+
+    /// Geocode an address, trying the formulations of the profile's `address`
+    /// templates in order until one finds a result
+    pub async fn query_with_address(
+        &self,
+        args: ExtraArgs,
+        address: &str,
+        parent: Option<&str>,
+    ) -> Result<serde_json::Value> {
+        let mut no_result = None;
+
+        for args in self.address_args(args, address, parent)? {
+            match self.query(args).await {
+                Err(e) if is_no_result(&e) => no_result = Some(e),
+                result => return result,
+            }
+        }
+
+        Err(no_result.expect("address_args gives at least one formulation"))
+    }
+
+    /// The request `query_with_city` would send, without sending it
+    pub fn resolve_with_city(&self, args: ExtraArgs, city: &str) -> Result<ResolvedRequest> {
+        self.resolve_with_address(args, city, None)
+    }
+
+    /// Geocode several addresses in one request to the profile's `batch` endpoint, each
+    /// with its first address formulation. The outer error fails the whole batch, the
+    /// inner ones single addresses.
+    pub async fn query_batch(
+        &self,
+        args: ExtraArgs,
+        addresses: &[(String, Option<String>)],
+    ) -> Result<Vec<Result<serde_json::Value>>> {
+        let (batch, args) = self.batch_args(args, addresses)?;
+
+        let res = self.req.send(&args).await?;
+
+        res.parse_with(&self.res, |body| {
+            let bodies = batch.split(body, addresses.len())?;
+            Ok(bodies.iter().map(|body| self.res.results(body)).collect())
+        })
+        .await
+    }
+
+    /// The request `query_batch` would send, without sending it
+    pub fn resolve_batch(
+        &self,
+        args: ExtraArgs,
+        addresses: &[(String, Option<String>)],
+    ) -> Result<ResolvedRequest> {
+        let (_, args) = self.batch_args(args, addresses)?;
+        self.req.resolve(&args)
+    }
+
+    fn batch_args(
+        &self,
+        mut args: ExtraArgs,
+        addresses: &[(String, Option<String>)],
+    ) -> Result<(&BatchProfile, ExtraArgs)> {
+        let batch = self
+            .batch
+            .as_ref()
+            .ok_or_else(|| anyhow!("profile has no batch endpoint"))?;
+        let profile = self.address.clone().unwrap_or_default();
+
+        let formulations = addresses
+            .iter()
+            .map(|(address, parent)| {
+                profile
+                    .formulations(address, parent.as_deref())
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("no address template applies to {}", address))
+            })
+            .collect::<Result<Vec<_>>>()?;
         args.query
-            .push(("address".into(), city.to_string() + "人民政府"));
+            .extend(batch.params(&profile.param, &formulations));
+
+        Ok((batch, args))
+    }
+
+    /// The first request `query_with_address` would send, without sending it
+    pub fn resolve_with_address(
+        &self,
+        args: ExtraArgs,
+        address: &str,
+        parent: Option<&str>,
+    ) -> Result<ResolvedRequest> {
+        let args = self.address_args(args, address, parent)?.remove(0);
+        self.req.resolve(&args)
+    }
+
+    /// The args of every formulation of an address
+    fn address_args(
+        &self,
+        args: ExtraArgs,
+        address: &str,
+        parent: Option<&str>,
+    ) -> Result<Vec<ExtraArgs>> {
+        let profile = self.address.clone().unwrap_or_default();
+        let formulations = profile.formulations(address, parent);
+        if formulations.is_empty() {
+            return Err(anyhow!("no address template applies to {}", address));
+        }
+
+        Ok(formulations
+            .into_iter()
+            .map(|formulation| {
+                let mut args = args.clone();
+                args.query.push((profile.param.clone(), formulation));
+                args
+            })
+            .collect())
+    }
+
+    /// Reverse geocode a coordinate given in `system`, or in the system the profile
+    /// expects when `system` is `None`
+    pub async fn query_with_location(
+        &self,
+        args: ExtraArgs,
+        lng: f64,
+        lat: f64,
+        system: Option<CoordSystem>,
+    ) -> Result<serde_json::Value> {
+        let args = self.location_args(args, lng, lat, system)?;
 
         let res = self.req.send(&args).await?;
 
         Ok(res.get_results(&self.res).await?)
     }
+
+    /// The request `query_with_location` would send, without sending it
+    pub fn resolve_with_location(
+        &self,
+        args: ExtraArgs,
+        lng: f64,
+        lat: f64,
+        system: Option<CoordSystem>,
+    ) -> Result<ResolvedRequest> {
+        self.req
+            .resolve(&self.location_args(args, lng, lat, system)?)
+    }
+
+    fn location_args(
+        &self,
+        mut args: ExtraArgs,
+        lng: f64,
+        lat: f64,
+        system: Option<CoordSystem>,
+    ) -> Result<ExtraArgs> {
+        let location = self.location.clone().unwrap_or_default();
+        let value = location.format(lng, lat, system)?;
+        args.query.push((location.param, value));
+        Ok(args)
+    }
+
+    /// Fetch every page of the profile's paginated search, handing the results new on each
+    /// page to `page` as it arrives. Results are picked like single ones when the profile
+    /// picks any, and handed on whole otherwise. Returns the number of pages fetched.
+    pub async fn query_pages(
+        &self,
+        args: ExtraArgs,
+        mut page: impl FnMut(Vec<serde_json::Value>) -> Result<()>,
+    ) -> Result<u64> {
+        let paginate = self
+            .req
+            .paginate
+            .as_ref()
+            .ok_or_else(|| anyhow!("profile has no paginate config"))?;
+        let (_, query, _) = self.req.generate(&args)?;
+        let mut pager = paginate.pager(&query);
+        let item = json!(format!("{}.0", paginate.items));
+
+        let mut pages = 0;
+        while let Some(number) = pager.next_page() {
+            let mut args = args.clone();
+            args.query
+                .push((paginate.page_param.clone(), number.to_string()));
+
+            let res = self.req.send(&args).await?;
+            let bodies = res.parse_with(&self.res, |body| pager.read(body)).await?;
+            let mut results = vec![];
+            for body in &bodies {
+                if self.res.pick_results.is_empty() {
+                    results.push(serde_json_lodash::get!(body.clone(), item.clone()));
+                    continue;
+                }
+                // a result holding none of the picked fields is dropped, not the search
+                match self.res.results(body) {
+                    Err(e) if is_no_result(&e) => continue,
+                    result => results.push(result?),
+                }
+            }
+
+            page(results)?;
+            pages += 1;
+        }
+
+        Ok(pages)
+    }
+
+    /// Send the request and evaluate the profile's `check` expectations against the response.
+    pub async fn check(&self, args: &ExtraArgs) -> Result<Vec<Assertion>> {
+        let check = self
+            .check
+            .as_ref()
+            .ok_or_else(|| anyhow!("profile has no check expectations"))?;
+
+        let start = Instant::now();
+        let res = self.req.send(args).await?;
+        let status = res.status();
+        let body = res.json().await?;
+        let latency = start.elapsed();
+
+        Ok(check.assert(status, &body, latency))
+    }
+}
+
+/// A chain's profiles, ready to be tried in order
+#[derive(Debug, Clone)]
+pub struct Chain {
+    /// The profiles with their names
+    pub links: Vec<(String, GeoCodingProfile)>,
+    pub accept: Acceptance,
+}
+
+/// The answer of a chain
+#[derive(Debug, Clone)]
+pub struct ChainAnswer<'a> {
+    /// The name of the profile which answered
+    pub name: &'a str,
+    pub profile: &'a GeoCodingProfile,
+    pub results: Value,
+    /// False when no profile met the acceptance criteria and the first answer was kept
+    pub accepted: bool,
+}
+
+impl Chain {
+    /// The profile tried first
+    pub fn first(&self) -> &GeoCodingProfile {
+        &self.links[0].1
+    }
+
+    pub async fn query_with_city(&self, args: ExtraArgs, city: &str) -> Result<ChainAnswer<'_>> {
+        self.query_with_address(args, city, None).await
+    }
+
+    pub async fn query_with_address(
+        &self,
+        args: ExtraArgs,
+        address: &str,
+        parent: Option<&str>,
+    ) -> Result<ChainAnswer<'_>> {
+        self.run(|profile| profile.query_with_address(args.clone(), address, parent))
+            .await
+    }
+
+    pub async fn query_with_location(
+        &self,
+        args: ExtraArgs,
+        lng: f64,
+        lat: f64,
+        system: Option<CoordSystem>,
+    ) -> Result<ChainAnswer<'_>> {
+        self.run(|profile| profile.query_with_location(args.clone(), lng, lat, system))
+            .await
+    }
+
+    /// Run `query` through the profiles until an answer is accepted. When none is,
+    /// the first answer is kept, and when no profile answered the last error is returned.
+    async fn run<'a, F, Fut>(&'a self, query: F) -> Result<ChainAnswer<'a>>
+    where
+        F: Fn(&'a GeoCodingProfile) -> Fut,
+        Fut: Future<Output = Result<Value>>,
+    {
+        let mut first = None;
+        let mut error = None;
+
+        for (name, profile) in &self.links {
+            match query(profile).await {
+                Result::Ok(results) => {
+                    let accepted = self.accept.accepts(&profile.res, &results);
+                    let answer = ChainAnswer {
+                        name,
+                        profile,
+                        results,
+                        accepted,
+                    };
+                    if accepted {
+                        return Ok(answer);
+                    }
+                    first.get_or_insert(answer);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        match (first, error) {
+            (Some(answer), _) => Ok(answer),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(anyhow!("the chain has no profiles")),
+        }
+    }
+}
+
+fn is_no_result(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ApiError>()
+        .is_some_and(|e| e.kind == ApiErrorKind::NoResult)
 }
 
 impl ValidateConfig for GeoCodingProfile {
     fn validate(&self) -> Result<()> {
         self.req.validate().context("req failed to validate")?;
+        if let Some(batch) = &self.batch {
+            batch.validate().context("batch failed to validate")?;
+        }
 
         Ok(())
     }
@@ -70,10 +503,266 @@ pub struct GeoCodingProfile {
 
     #[serde(skip_serializing_if = "is_default", default)]
     pub res: ResponseProfile,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub check: Option<CheckProfile>,
+
+    /// How addresses are sent when geocoding, `address=<address>人民政府` by default
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub address: Option<AddressProfile>,
+
+    /// How coordinates are sent when reverse geocoding
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub location: Option<LocationProfile>,
+
+    /// How several addresses are sent in one request, when the provider can
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub batch: Option<BatchProfile>,
 }
 
 impl GeoCodingProfile {
     pub fn new(req: RequestProfile, res: ResponseProfile) -> Self {
-        Self { req, res }
+        Self {
+            req,
+            res,
+            check: None,
+            address: None,
+            location: None,
+            batch: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Replay;
+
+    const CONFIG: &str = r#"
+# not recorded, every request fails
+unrecorded:
+  req:
+    url: https://api.map.baidu.com/geocoding/v3/
+    params:
+      city: 北京
+      ret_coordtype: gcj02ll
+      output: json
+  res:
+    level: '地址结构'
+    pick_results:
+      result.level: '地址结构'
+baidu:
+  req:
+    url: https://api.map.baidu.com/geocoding/v3/
+    params:
+      ret_coordtype: gcj02ll
+      output: json
+  res:
+    success:
+      path: status
+      equals: 0
+    level: '地址结构'
+    pick_results:
+      result.level: '地址结构'
+# the bare city name finds nothing, the government's name does
+templated:
+  req:
+    url: https://api.map.baidu.com/geocoding/v3/
+    params:
+      ret_coordtype: gcj02ll
+      output: json
+  address:
+    templates: ['{address}', '{parent}{address}', '{address}人民政府']
+  res:
+    success:
+      path: status
+      equals: 0
+    pick_results:
+      result.level: '地址结构'
+# AMap's batch geocoding, fills the fields of addresses it can't find with empty lists
+batched:
+  req:
+    url: https://restapi.amap.com/v3/geocode/geo
+    params:
+      output: JSON
+  address:
+    templates: ['{address}']
+  batch:
+    size: 3
+    params:
+      batch: true
+    results: geocodes
+  res:
+    success:
+      path: infocode
+      equals: '10000'
+    pick_results:
+      geocodes.0.adcode: '区划代码'
+      geocodes.0.level: '地址结构'
+# Baidu's place search, two results a page
+paged:
+  req:
+    url: https://api.map.baidu.com/place/v2/search
+    params:
+      query: 人民政府
+      region: 白银
+      output: json
+      page_size: 2
+    paginate:
+      page_param: page_num
+      size_param: page_size
+      total: total
+      items: results
+      dedup_key: uid
+  res:
+    success:
+      path: status
+      equals: 0
+    pick_results:
+      results.0.name: '名称'
+      results.0.uid: 'uid'
+fallback:
+  chain:
+    profiles: [unrecorded, baidu]
+    accept:
+      levels: [政府机构]
+strict:
+  chain:
+    profiles: [baidu, unrecorded]
+    accept:
+      levels: [门址]
+"#;
+
+    fn config() -> GeoCodingConfig {
+        let replay = Replay::open("fixtures/geo_coding.jsonl").unwrap();
+        GeoCodingConfig::from_yaml(CONFIG)
+            .unwrap()
+            .with_replay(Some(Arc::new(replay)))
+    }
+
+    #[test]
+    fn chains_should_be_parsed_and_validated() {
+        let config = config();
+        assert_eq!(config.profiles.len(), 5);
+        assert_eq!(config.chains["fallback"].profiles, ["unrecorded", "baidu"]);
+
+        // a plain profile is a chain of itself
+        let chain = config.get_chain("baidu").unwrap();
+        assert_eq!(chain.links.len(), 1);
+        assert_eq!(chain.accept, Acceptance::default());
+
+        let e =
+            GeoCodingConfig::from_yaml("broken:\n  chain:\n    profiles: [missing]\n").unwrap_err();
+        assert_eq!(e.to_string(), "chain broken uses unknown profile missing");
+    }
+
+    #[tokio::test]
+    async fn chain_should_fall_back_until_accepted() {
+        let config = config();
+
+        let chain = config.get_chain("fallback").unwrap();
+        let answer = chain
+            .query_with_city(ExtraArgs::default(), "白银市")
+            .await
+            .unwrap();
+        assert_eq!((answer.name, answer.accepted), ("baidu", true));
+        assert_eq!(answer.results["地址结构"], "政府机构");
+
+        // nothing is accepted, the first answer is kept
+        let chain = config.get_chain("strict").unwrap();
+        let answer = chain
+            .query_with_city(ExtraArgs::default(), "白银市")
+            .await
+            .unwrap();
+        assert_eq!((answer.name, answer.accepted), ("baidu", false));
+
+        // no profile answered
+        assert!(chain
+            .query_with_city(ExtraArgs::default(), "定西市")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn address_templates_should_be_tried_in_order() {
+        let config = config();
+        let profile = config.get_profile("templated").unwrap();
+
+        let req = profile
+            .resolve_with_address(ExtraArgs::default(), "白银市", Some("甘肃省"))
+            .unwrap();
+        assert!(req
+            .url
+            .as_str()
+            .contains("address=%E7%99%BD%E9%93%B6%E5%B8%82&"));
+
+        // `{parent}` is skipped without a parent
+        let results = profile
+            .query_with_city(ExtraArgs::default(), "白银市")
+            .await
+            .unwrap();
+        assert_eq!(results["地址结构"], "政府机构");
+
+        // errors other than finding nothing are not retried
+        let e = profile
+            .query_with_city(ExtraArgs::default(), "定西市")
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("?address=定西市&"));
+    }
+
+    #[tokio::test]
+    async fn batch_should_split_results_by_position() {
+        let config = config();
+        let profile = config.get_profile("batched").unwrap();
+        let addresses = ["白银市", "不存在的地方", "定西市"].map(|a| (a.to_string(), None));
+
+        let results = profile
+            .query_batch(ExtraArgs::default(), &addresses)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap()["区划代码"], "620400");
+        assert_eq!(results[2].as_ref().unwrap()["区划代码"], "621100");
+
+        // a failed address fails its own row only
+        let e = results[1].as_ref().unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().unwrap().kind,
+            ApiErrorKind::NoResult
+        );
+
+        // the batch as a whole fails when results go missing
+        let e = profile
+            .query_batch(ExtraArgs::default(), &addresses[..2])
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("2 addresses"));
+    }
+
+    #[tokio::test]
+    async fn query_pages_should_merge_pages() {
+        let config = config();
+        let profile = config.get_profile("paged").unwrap();
+
+        let mut pages = vec![];
+        let count = profile
+            .query_pages(ExtraArgs::default(), |results| {
+                pages.push(results);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // the total of 4 asks for two pages, 白银区人民政府 moved onto both of them
+        let names = pages
+            .iter()
+            .map(|page| page.iter().map(|r| r["名称"].as_str().unwrap()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            names,
+            [vec!["白银市人民政府", "白银区人民政府"], vec!["平川区人民政府"]]
+        );
     }
 }
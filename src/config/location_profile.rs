@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::CoordSystem;
+
+/// Describes how a reverse geocoding provider takes a coordinate, e.g. Baidu wants
+/// `location=lat,lng` while AMap wants `location=lng,lat`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LocationProfile {
+    /// The query param the coordinate is sent in
+    #[serde(default = "default_param")]
+    pub param: String,
+
+    /// Which of longitude and latitude goes first
+    #[serde(default)]
+    pub order: LocationOrder,
+
+    /// The coordinate system the provider expects, inputs in another system are converted to it
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub system: Option<CoordSystem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationOrder {
+    #[default]
+    LngLat,
+    LatLng,
+}
+
+fn default_param() -> String {
+    "location".into()
+}
+
+impl Default for LocationProfile {
+    fn default() -> Self {
+        Self {
+            param: default_param(),
+            order: LocationOrder::default(),
+            system: None,
+        }
+    }
+}
+
+impl LocationProfile {
+    /// Format a coordinate given in `from` as the provider wants it
+    pub fn format(&self, lng: f64, lat: f64, from: Option<CoordSystem>) -> Result<String> {
+        let (lng, lat) = match (from, self.system) {
+            (Some(from), Some(to)) => from.convert(lng, lat, to),
+            (Some(from), None) => {
+                return Err(anyhow!(
+                    "inputs are in {:?} but the profile doesn't set `location.system`",
+                    from
+                ))
+            }
+            (None, _) => (lng, lat),
+        };
+
+        Ok(match self.order {
+            LocationOrder::LngLat => format!("{},{}", lng, lat),
+            LocationOrder::LatLng => format!("{},{}", lat, lng),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_should_work() {
+        let location: LocationProfile = serde_yaml::from_str("order: lat_lng").unwrap();
+        assert_eq!(location.param, "location");
+        assert_eq!(
+            location.format(104.14, 36.54, None).unwrap(),
+            "36.54,104.14"
+        );
+        assert!(location
+            .format(104.14, 36.54, Some(CoordSystem::Wgs84))
+            .is_err());
+
+        let location = LocationProfile {
+            system: Some(CoordSystem::Gcj02),
+            ..Default::default()
+        };
+        assert_eq!(
+            location
+                .format(104.14, 36.54, Some(CoordSystem::Gcj02))
+                .unwrap(),
+            "104.14,36.54"
+        );
+
+        let (lng, lat) = CoordSystem::Wgs84.convert(104.14, 36.54, CoordSystem::Gcj02);
+        assert_eq!(
+            location
+                .format(104.14, 36.54, Some(CoordSystem::Wgs84))
+                .unwrap(),
+            format!("{},{}", lng, lat)
+        );
+    }
+}
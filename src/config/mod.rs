@@ -2,24 +2,42 @@ use anyhow::{anyhow, Ok, Result};
 use async_trait::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
-    Client, Method, Response, Url,
+    Client, Method, Response, StatusCode, Url,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use serde_json_lodash::get;
-use serde_urlencoded;
-use serde_yaml;
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 use tokio::fs;
 
-use crate::{cli::KeyValType, ExtraArgs};
+use crate::{
+    cache::build_response, cli::KeyValType, ApiError, ApiErrorKind, Cache, CachedResponse,
+    ExtraArgs, ProfileRecorder, Replay, RequestStats,
+};
 
+mod address_profile;
+mod batch_profile;
+mod chain_profile;
+mod check_profile;
 mod geo_coding;
+mod location_profile;
+mod paginate_profile;
+mod quality_rule;
 mod response_profile;
 
-pub use geo_coding::{GeoCodingConfig, GeoCodingProfile};
-pub use response_profile::ResponseProfile;
+pub use address_profile::AddressProfile;
+pub use batch_profile::BatchProfile;
+pub use chain_profile::{Acceptance, ChainProfile};
+pub use check_profile::{Assertion, CheckProfile, NumberRange};
+pub use geo_coding::{Chain, ChainAnswer, GeoCodingConfig, GeoCodingProfile};
+pub use location_profile::{LocationOrder, LocationProfile};
+pub use paginate_profile::{Pager, PaginateProfile};
+pub use quality_rule::{Quality, QualityOp, QualityRule};
+pub use response_profile::{CoordinateFields, ResponseProfile, SuccessProfile};
 
 #[async_trait]
 pub trait LoadConfig
@@ -67,13 +85,61 @@ pub struct RequestProfile {
 
     #[serde(skip_serializing_if = "empty_json_value", default)]
     pub body: Option<serde_json::Value>,
+
+    /// How to page through the results of a search
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub paginate: Option<PaginateProfile>,
+
+    /// Serves and stores responses instead of always hitting the network
+    #[serde(skip)]
+    pub cache: Option<Arc<Cache>>,
+
+    /// Logs every request sent over the network
+    #[serde(skip)]
+    pub recorder: Option<ProfileRecorder>,
+
+    /// Serves recorded responses, no request is sent over the network
+    #[serde(skip)]
+    pub replay: Option<Arc<Replay>>,
+
+    /// Counts the requests sent over the network
+    #[serde(skip)]
+    pub stats: Option<Arc<RequestStats>>,
 }
 
-pub struct ResponseExt(Response);
+/// A request with the extra args merged in, as it would be sent
+#[derive(Debug, Clone)]
+pub struct ResolvedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+}
+
+pub struct ResponseExt {
+    res: Response,
+    /// Where to store the response once it is known to be a good one
+    cache: Option<(Arc<Cache>, String)>,
+}
 
 impl RequestProfile {
     pub async fn send(&self, args: &ExtraArgs) -> Result<ResponseExt> {
-        let (headers, query, body) = self.generate(&args)?;
+        let (headers, query, body) = self.generate(args)?;
+        let key = Cache::key(&self.method, &self.url, &query, &body);
+
+        if let Some(replay) = &self.replay {
+            return Ok(ResponseExt::new(replay.get(&key)?));
+        }
+
+        let cache = match &self.cache {
+            Some(cache) => {
+                if let Some(entry) = cache.get(&key)? {
+                    return Ok(ResponseExt::new(entry.to_response()?));
+                }
+                Some((cache.clone(), key.clone()))
+            }
+            None => None,
+        };
 
         let client = Client::new();
         let req = client
@@ -82,10 +148,62 @@ impl RequestProfile {
             .headers(headers)
             .body(body)
             .build()?;
+        let url = req.url().clone();
+
+        let (sent_at, start) = (SystemTime::now(), Instant::now());
+        let res = client.execute(req).await;
+        // a request which got no answer may still have used up quota
+        if let Some(stats) = &self.stats {
+            stats.record(start.elapsed());
+        }
+        let res = res?;
+
+        let res = match &self.recorder {
+            Some(recorder) => {
+                let (status, headers) = (res.status(), res.headers().clone());
+                let text = res.text().await?;
+                let recorded = recorder.record(
+                    args.row_id.as_deref(),
+                    &self.method,
+                    &url,
+                    &key,
+                    status,
+                    &headers,
+                    &text,
+                    sent_at,
+                    start.elapsed(),
+                );
+                // the request already used quota, a log we can't write to shouldn't fail it
+                if let Err(e) = recorded {
+                    eprintln!("warning: failed to record request: {:#}", e);
+                }
+
+                // the body was consumed for the log, hand on an identical response
+                build_response(status.as_u16(), &headers, &text)?
+            }
+            None => res,
+        };
+
+        Ok(ResponseExt { res, cache })
+    }
+
+    /// Merge the extra args into the request without sending it
+    pub fn resolve(&self, args: &ExtraArgs) -> Result<ResolvedRequest> {
+        let (headers, query, body) = self.generate(args)?;
+        let req = Client::new()
+            .request(self.method.clone(), self.url.clone())
+            .query(&query)
+            .build()?;
 
-        let res = client.execute(req).await?;
+        // an empty object is what every request without a body sends
+        let body = Some(body).filter(|b| !b.is_empty() && b != "{}");
 
-        Ok(ResponseExt(res))
+        Ok(ResolvedRequest {
+            method: self.method.clone(),
+            url: req.url().clone(),
+            headers,
+            body,
+        })
     }
 
     fn generate(&self, args: &ExtraArgs) -> Result<(HeaderMap, serde_json::Value, String)> {
@@ -145,6 +263,11 @@ impl RequestProfile {
             params,
             headers,
             body,
+            paginate: None,
+            cache: None,
+            recorder: None,
+            replay: None,
+            stats: None,
         }
     }
 
@@ -209,13 +332,61 @@ impl FromStr for RequestProfile {
     }
 }
 
+impl ResolvedRequest {
+    /// An equivalent curl command line
+    pub fn to_curl(&self) -> String {
+        let mut cmd = format!("curl -X {} {}", self.method, shell_quote(self.url.as_str()));
+        for (name, value) in &self.headers {
+            let header = format!("{}: {}", name, value.to_str().unwrap_or_default());
+            cmd.push_str(&format!(" \\\n  -H {}", shell_quote(&header)));
+        }
+        if let Some(body) = &self.body {
+            cmd.push_str(&format!(" \\\n  --data-raw {}", shell_quote(body)));
+        }
+        cmd
+    }
+}
+
+impl fmt::Display for ResolvedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", self.method, self.url)?;
+        for (name, value) in &self.headers {
+            writeln!(f, "{}: {}", name, value.to_str().unwrap_or_default())?;
+        }
+        if let Some(body) = &self.body {
+            writeln!(f)?;
+            writeln!(f, "{}", body)?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+/// Quote a string for a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
 impl ResponseExt {
+    pub fn new(res: Response) -> Self {
+        Self { res, cache: None }
+    }
+
     pub fn into_inner(self) -> Response {
-        self.0
+        self.res
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.res.status()
+    }
+
+    /// Consume the response and parse its body as JSON
+    pub async fn json(self) -> Result<serde_json::Value> {
+        let text = self.res.text().await?;
+        Ok(serde_json::from_str(&text)?)
     }
 
     pub fn get_header_keys(&self) -> Vec<String> {
-        self.0
+        self.res
             .headers()
             .keys()
             .map(|k| k.as_str().to_string())
@@ -223,10 +394,51 @@ impl ResponseExt {
     }
 
     pub async fn get_results(self, profile: &ResponseProfile) -> Result<serde_json::Value> {
-        let res = self.0;
-        let text = res.text().await?;
+        self.parse_with(profile, |body| profile.results(body)).await
+    }
 
-        Ok(get_json_value(&text, &profile.pick_results)?)
+    /// Check the response against the profile's `success` rule and hand the body to `parse`.
+    /// The response is only cached when `parse` succeeds.
+    pub async fn parse_with<T>(
+        self,
+        profile: &ResponseProfile,
+        parse: impl FnOnce(&serde_json::Value) -> Result<T>,
+    ) -> Result<T> {
+        let status = self.status();
+        if !status.is_success() {
+            let text = self.res.text().await.unwrap_or_default();
+            let kind = ApiErrorKind::from_http_status(status.as_u16());
+            let message = Some(text.chars().take(200).collect()).filter(|s: &String| !s.is_empty());
+            let error = ApiError::new(kind, json!(null), message).with_http_status(status.as_u16());
+            return Err(error.into());
+        }
+
+        let headers = self
+            .res
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let text = self.res.text().await?;
+        let body: serde_json::Value = serde_json::from_str(&text)?;
+
+        profile
+            .check_success(&body)
+            .map_err(|e| e.with_http_status(status.as_u16()))?;
+
+        let parsed = parse(&body).map_err(|e| match e.downcast::<ApiError>() {
+            Result::Ok(api_error) => api_error.with_http_status(status.as_u16()).into(),
+            Err(e) => e,
+        })?;
+
+        // the request already succeeded and used quota, a cache we can't write to shouldn't fail it
+        if let Some((cache, key)) = self.cache {
+            if let Err(e) = cache.put(&CachedResponse::new(key, status.as_u16(), headers, text)) {
+                eprintln!("warning: failed to cache response: {:#}", e);
+            }
+        }
+
+        Ok(parsed)
     }
 }
 
@@ -240,8 +452,32 @@ pub fn get_status_text(res: &Response) -> Result<String> {
     Ok(format!("{:?} {}", res.version(), res.status()))
 }
 
+/// The list at a dotted `path` of a response body, with one copy of the body per element
+/// holding only that element, so `pick_results` paths like `results.0.name` work on each
+pub(crate) fn split_results(
+    body: &serde_json::Value,
+    path: &str,
+) -> Result<Vec<serde_json::Value>> {
+    let pointer = format!("/{}", path.replace('.', "/"));
+    let results = body
+        .pointer(&pointer)
+        .and_then(serde_json::Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("response has no `{}` array", path))?;
+
+    Ok(results
+        .iter()
+        .map(|result| {
+            let mut body = body.clone();
+            if let Some(results) = body.pointer_mut(&pointer) {
+                *results = json!([result]);
+            }
+            body
+        })
+        .collect())
+}
+
 fn empty_json_value(v: &Option<serde_json::Value>) -> bool {
-    v.as_ref().map_or(true, |v| {
+    v.as_ref().is_none_or(|v| {
         if v.is_object() {
             if let Some(obj) = v.as_object() {
                 return obj.is_empty();
@@ -252,13 +488,51 @@ fn empty_json_value(v: &Option<serde_json::Value>) -> bool {
     })
 }
 
-fn get_json_value(text: &str, pick_results: &HashMap<String, String>) -> Result<serde_json::Value> {
-    let obj: HashMap<String, serde_json::Value> = serde_json::from_str(text)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_should_merge_extra_args() {
+        let req = RequestProfile::new(
+            Method::GET,
+            Url::parse("https://api.map.baidu.com/geocoding/v3/").unwrap(),
+            Some(json!({ "output": "json" })),
+            HeaderMap::new(),
+            None,
+        );
+        let args = ExtraArgs {
+            query: vec![("address".into(), "白银市".into())],
+            ..Default::default()
+        };
+
+        let resolved = req.resolve(&args).unwrap();
+        assert_eq!(resolved.method, Method::GET);
+        assert_eq!(
+            resolved.url.query_pairs().collect::<Vec<_>>(),
+            vec![
+                ("address".into(), "白银市".into()),
+                ("output".into(), "json".into())
+            ]
+        );
+        assert_eq!(resolved.headers[CONTENT_TYPE], "application/json");
+        assert_eq!(resolved.body, None);
+    }
 
-    let res = pick_results
-        .into_iter()
-        .map(move |(path, name)| (name, get!(json!(obj), json!(path))))
-        .collect::<serde_json::Value>();
+    #[test]
+    fn to_curl_should_quote_arguments() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-note", HeaderValue::from_static("it's"));
+        let resolved = ResolvedRequest {
+            method: Method::POST,
+            url: "https://example.com/?a=1&b=2".parse().unwrap(),
+            headers,
+            body: Some(r#"{"q":"x"}"#.into()),
+        };
 
-    Ok(res)
+        assert_eq!(
+            resolved.to_curl(),
+            "curl -X POST 'https://example.com/?a=1&b=2' \\\n  -H 'x-note: it'\\''s' \\\n  --data-raw '{\"q\":\"x\"}'"
+        );
+    }
 }
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_lodash::get;
+use std::collections::HashSet;
+
+use super::split_results;
+use crate::{cell_text, output::value_to_f64};
+
+/// Describes how a search pages through its results, e.g. Baidu's place search with
+/// `page_num` and `page_size` params and a `total` in every response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PaginateProfile {
+    /// The query param holding the page number
+    pub page_param: String,
+
+    /// The query param holding the page size, read from the request's params
+    pub size_param: String,
+
+    /// The number of the first page, 0 for Baidu and 1 for AMap
+    #[serde(default)]
+    pub first_page: u64,
+
+    /// Path of the total number of results in a response
+    pub total: String,
+
+    /// Path of the list of results on a page
+    pub items: String,
+
+    /// Stop after this many pages whatever the total says
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u64,
+
+    /// Path in a result identifying it, e.g. `uid`. Results seen on an earlier page are dropped.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dedup_key: Option<String>,
+}
+
+fn default_max_pages() -> u64 {
+    10
+}
+
+/// Walks the pages of one search
+#[derive(Debug)]
+pub struct Pager<'a> {
+    profile: &'a PaginateProfile,
+    /// The page size of the request, `None` when the request doesn't set one
+    size: Option<u64>,
+    /// The index of the next page, counted from 0
+    next: u64,
+    /// The number of pages the total asks for, known after the first page
+    pages: Option<u64>,
+    seen: HashSet<String>,
+}
+
+impl PaginateProfile {
+    /// Start paging through a request with these query params
+    pub fn pager(&self, query: &Value) -> Pager<'_> {
+        Pager {
+            profile: self,
+            size: value_to_f64(&query[&self.size_param]).map(|size| size as u64),
+            next: 0,
+            pages: None,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl<'a> Pager<'a> {
+    /// The page number to request next, `None` once every page was fetched
+    pub fn next_page(&self) -> Option<u64> {
+        let last = self.pages.unwrap_or(u64::MAX).min(self.profile.max_pages);
+        (self.next < last).then(|| self.profile.first_page + self.next)
+    }
+
+    /// Read a page, returning one body per result which wasn't on an earlier page
+    pub fn read(&mut self, body: &Value) -> Result<Vec<Value>> {
+        let profile = self.profile;
+        let bodies = split_results(body, &profile.items)?;
+        self.next += 1;
+
+        if self.pages.is_none() {
+            let total = get!(body.clone(), serde_json::json!(profile.total));
+            let total = value_to_f64(&total)
+                .ok_or_else(|| anyhow!("response has no total at `{}`", profile.total))?;
+            // without a page size, the first page shows how many results a page holds
+            let size = *self.size.get_or_insert(bodies.len() as u64);
+            self.pages = Some((total as u64).div_ceil(size.max(1)));
+        }
+        // a short page is the last one, whatever the total says
+        if (bodies.len() as u64) < self.size.unwrap_or_default().max(1) {
+            self.pages = Some(self.next);
+        }
+
+        let Some(key) = &profile.dedup_key else {
+            return Ok(bodies);
+        };
+        Ok(bodies
+            .into_iter()
+            .filter(|body| {
+                let item = get!(
+                    body.clone(),
+                    serde_json::json!(format!("{}.0.{}", profile.items, key))
+                );
+                let id = cell_text(&item);
+                id.is_empty() || self.seen.insert(id)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pager_should_stop_at_the_total_and_drop_duplicates() {
+        let paginate: PaginateProfile = serde_yaml::from_str(
+            r#"
+            page_param: page_num
+            size_param: page_size
+            total: total
+            items: results
+            dedup_key: uid
+            "#,
+        )
+        .unwrap();
+
+        let mut pager = paginate.pager(&json!({"query": "人民政府", "page_size": "2"}));
+        assert_eq!(pager.next_page(), Some(0));
+
+        let page = json!({"total": 3, "results": [{"uid": "a"}, {"uid": "b"}]});
+        let bodies = pager.read(&page).unwrap();
+        assert_eq!(bodies[1], json!({"total": 3, "results": [{"uid": "b"}]}));
+        assert_eq!(pager.next_page(), Some(1));
+
+        // `b` moved to the second page while we were paging
+        let page = json!({"total": 3, "results": [{"uid": "b"}, {"uid": "c"}]});
+        let bodies = pager.read(&page).unwrap();
+        assert_eq!(bodies, [json!({"total": 3, "results": [{"uid": "c"}]})]);
+        assert_eq!(pager.next_page(), None);
+    }
+
+    #[test]
+    fn pager_should_respect_max_pages() {
+        let paginate = PaginateProfile {
+            page_param: "page".into(),
+            size_param: "offset".into(),
+            first_page: 1,
+            total: "count".into(),
+            items: "pois".into(),
+            max_pages: 2,
+            dedup_key: None,
+        };
+
+        let mut pager = paginate.pager(&json!({}));
+        pager
+            .read(&json!({"count": "100", "pois": [{}, {}]}))
+            .unwrap();
+        assert_eq!(pager.next_page(), Some(2));
+        pager
+            .read(&json!({"count": "100", "pois": [{}, {}]}))
+            .unwrap();
+        assert_eq!(pager.next_page(), None);
+
+        // an empty page ends the search early
+        let mut pager = paginate.pager(&json!({}));
+        pager.read(&json!({"count": "100", "pois": []})).unwrap();
+        assert_eq!(pager.next_page(), None);
+
+        // and so does a short one
+        let mut pager = paginate.pager(&json!({"offset": 3}));
+        pager
+            .read(&json!({"count": "100", "pois": [{}, {}]}))
+            .unwrap();
+        assert_eq!(pager.next_page(), None);
+    }
+}
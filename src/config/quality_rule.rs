@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_lodash::get;
+use std::{fmt, str::FromStr};
+
+use super::response_profile::loosely_equals;
+use crate::output::value_to_f64;
+
+/// A rule a response must meet for its result to be trusted, written as
+/// `<path> <op> <value>`, e.g. `result.confidence >= 50` or `result.level in [门址, 道路]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct QualityRule {
+    /// Path of the field in the response body
+    pub path: String,
+    pub op: QualityOp,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    In,
+}
+
+/// How far a result can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    /// Every quality rule is met
+    Ok,
+    /// Some quality rule is not met
+    Low,
+    /// There is no result to judge
+    Failed,
+}
+
+impl QualityRule {
+    pub fn check(&self, body: &Value) -> bool {
+        let field = get!(body.clone(), serde_json::json!(self.path));
+        let compare =
+            |ok: fn(f64, f64) -> bool| match (value_to_f64(&field), value_to_f64(&self.value)) {
+                (Some(a), Some(b)) => ok(a, b),
+                _ => false,
+            };
+
+        match self.op {
+            QualityOp::Eq => loosely_equals(&field, &self.value),
+            QualityOp::Ne => !loosely_equals(&field, &self.value),
+            QualityOp::Gt => compare(|a, b| a > b),
+            QualityOp::Ge => compare(|a, b| a >= b),
+            QualityOp::Lt => compare(|a, b| a < b),
+            QualityOp::Le => compare(|a, b| a <= b),
+            QualityOp::In => self
+                .value
+                .as_array()
+                .is_some_and(|values| values.iter().any(|v| loosely_equals(&field, v))),
+        }
+    }
+}
+
+impl Quality {
+    /// Grade a response body by a set of rules
+    pub fn grade(rules: &[QualityRule], body: &Value) -> Self {
+        match rules.iter().all(|rule| rule.check(body)) {
+            true => Self::Ok,
+            false => Self::Low,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Low => "low",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for QualityRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(3, char::is_whitespace);
+        let (Some(path), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(anyhow!("quality rule `{}` is not `<path> <op> <value>`", s));
+        };
+
+        let op = match op {
+            "==" => QualityOp::Eq,
+            "!=" => QualityOp::Ne,
+            ">" => QualityOp::Gt,
+            ">=" => QualityOp::Ge,
+            "<" => QualityOp::Lt,
+            "<=" => QualityOp::Le,
+            "in" => QualityOp::In,
+            op => return Err(anyhow!("unknown operator `{}` in quality rule `{}`", op, s)),
+        };
+
+        // the value is written the way yaml would write it
+        let value: Value = serde_yaml::from_str(value.trim())?;
+        if op == QualityOp::In && !value.is_array() {
+            return Err(anyhow!("quality rule `{}` needs a list after `in`", s));
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            op,
+            value,
+        })
+    }
+}
+
+impl TryFrom<String> for QualityRule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse().map_err(|e: anyhow::Error| e.to_string())
+    }
+}
+
+impl From<QualityRule> for String {
+    fn from(rule: QualityRule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl fmt::Display for QualityRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            QualityOp::Eq => "==",
+            QualityOp::Ne => "!=",
+            QualityOp::Gt => ">",
+            QualityOp::Ge => ">=",
+            QualityOp::Lt => "<",
+            QualityOp::Le => "<=",
+            QualityOp::In => "in",
+        };
+        write!(f, "{} {} {}", self.path, op, self.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quality_rules_should_work() {
+        let rules: Vec<QualityRule> = serde_yaml::from_str(
+            r#"
+            - result.confidence >= 50
+            - result.level in [门址, 道路, 兴趣点]
+            - result.precise == 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules[1].value, json!(["门址", "道路", "兴趣点"]));
+
+        let body = json!({"result": {"confidence": 80, "level": "门址", "precise": 1}});
+        assert_eq!(Quality::grade(&rules, &body), Quality::Ok);
+
+        let body = json!({"result": {"confidence": "80", "level": "城市", "precise": 1}});
+        assert_eq!(Quality::grade(&rules, &body), Quality::Low);
+
+        // a missing field fails its rule
+        let body = json!({"result": {"level": "门址", "precise": 1}});
+        assert_eq!(Quality::grade(&rules, &body), Quality::Low);
+    }
+
+    #[test]
+    fn invalid_quality_rules_should_be_rejected() {
+        assert!("result.confidence".parse::<QualityRule>().is_err());
+        assert!("result.confidence ~ 50".parse::<QualityRule>().is_err());
+        assert!("result.level in 门址".parse::<QualityRule>().is_err());
+    }
+}
@@ -1,14 +1,371 @@
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_json_lodash::get;
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+use super::quality_rule::{Quality, QualityRule};
+use crate::{output::value_to_f64, ApiError, ApiErrorKind, CoordSystem};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct ResponseProfile {
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub pick_results: HashMap<String, String>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub success: Option<SuccessProfile>,
+
+    /// Paths removed from the body before it is compared, e.g. by `diff`
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub skip_body: Vec<String>,
+
+    /// The picked result names holding the longitude and latitude
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub coordinates: Option<CoordinateFields>,
+
+    /// The picked result name holding the precision level, e.g. 门址 or 城市
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub level: Option<String>,
+
+    /// The picked result name holding the provider's confidence in the result
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub confidence: Option<String>,
+
+    /// What `confidence` is multiplied by to put it on a 0–100 scale, e.g. 10 for
+    /// Tencent's 1–10 reliability. Defaults to 1
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub confidence_scale: Option<f64>,
+
+    /// The picked result name holding the GB/T 2260 code of the place found
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub adcode: Option<String>,
+
+    /// Rules the response must meet, adds a `quality` result of `ok` or `low`
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub quality: Vec<QualityRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CoordinateFields {
+    pub lng: String,
+    pub lat: String,
+
+    /// A picked result holding the point as one `lng,lat` string, as AMap answers.
+    /// It is split into the `lng` and `lat` results.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub combined: Option<String>,
+
+    /// The coordinate system the provider answers in, e.g. `gcj02` or `bd09ll`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub system: Option<CoordSystem>,
+}
+
+/// Describes how to tell a successful provider response from an error one,
+/// for providers which answer `200 OK` with an error code in the body.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SuccessProfile {
+    /// Path of the status code in the response body, e.g. `status`
+    pub path: String,
+
+    /// The value `path` holds when the request succeeded
+    pub equals: serde_json::Value,
+
+    /// Path of the human readable error message, e.g. `message`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub message_path: Option<String>,
+
+    /// Maps error kinds to the status codes the provider uses for them.
+    /// When empty, Baidu's documented status codes and messages are used.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub errors: HashMap<ApiErrorKind, Vec<serde_json::Value>>,
 }
 
 impl ResponseProfile {
     pub fn new(pick_results: HashMap<String, String>) -> Self {
-        Self { pick_results }
+        Self {
+            pick_results,
+            ..Default::default()
+        }
+    }
+
+    /// Pick the configured `pick_results` out of a response body
+    pub fn pick(&self, body: &serde_json::Value) -> serde_json::Value {
+        self.pick_results
+            .iter()
+            .map(|(path, name)| (name, get!(body.clone(), serde_json::json!(path))))
+            .collect()
+    }
+
+    /// The picked results of a successful response body, graded by the `quality` rules
+    pub fn results(&self, body: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let mut results = self.pick(body);
+
+        // the provider said ok, but nothing we asked for is in the response. AMap fills
+        // the fields of an address it couldn't find in a batch with empty lists.
+        let is_empty = |v: &serde_json::Value| {
+            v.is_null() || v.as_str() == Some("") || v.as_array().is_some_and(Vec::is_empty)
+        };
+        if self.success.is_some()
+            && !self.pick_results.is_empty()
+            && results
+                .as_object()
+                .is_some_and(|obj| obj.values().all(is_empty))
+        {
+            return Err(ApiError::new(ApiErrorKind::NoResult, json!(null), None).into());
+        }
+
+        if let Some(fields) = &self.coordinates {
+            fields.split(&mut results);
+        }
+
+        if !self.quality.is_empty() {
+            let quality = Quality::grade(&self.quality, body);
+            results["quality"] = json!(quality.as_str());
+        }
+
+        Ok(results)
+    }
+
+    /// The names of the results `results` can return, in no particular order
+    pub fn result_names(&self) -> Vec<&str> {
+        let combined = self
+            .coordinates
+            .as_ref()
+            .and_then(|c| c.combined.as_deref());
+        let mut names = self
+            .pick_results
+            .values()
+            .map(String::as_str)
+            .filter(|name| Some(*name) != combined)
+            .collect::<Vec<_>>();
+        if let Some(fields) = self.coordinates.as_ref().filter(|c| c.combined.is_some()) {
+            names.extend([fields.lng.as_str(), fields.lat.as_str()]);
+        }
+        if !self.quality.is_empty() {
+            names.push("quality");
+        }
+        names
+    }
+
+    /// The confidence in picked `results` on a 0–100 scale, if the profile picks one
+    pub fn confidence(&self, results: &serde_json::Value) -> Option<f64> {
+        let confidence = value_to_f64(results.get(self.confidence.as_ref()?)?)?;
+        Some(confidence * self.confidence_scale.unwrap_or(1.0))
+    }
+
+    /// Remove the `skip_body` paths from a response body
+    pub fn strip(&self, body: &mut serde_json::Value) {
+        for path in &self.skip_body {
+            let mut keys = path.split('.').collect::<Vec<_>>();
+            let last = keys.pop().unwrap_or_default();
+
+            let parent = keys
+                .into_iter()
+                .try_fold(&mut *body, |v, key| v.get_mut(key));
+            if let Some(serde_json::Value::Object(obj)) = parent {
+                obj.remove(last);
+            }
+        }
+    }
+
+    /// Check the response body against the `success` rule, if any.
+    pub fn check_success(&self, body: &serde_json::Value) -> Result<(), ApiError> {
+        match &self.success {
+            Some(success) => success.check(body),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CoordinateFields {
+    pub fn new(lng: impl Into<String>, lat: impl Into<String>) -> Self {
+        Self {
+            lng: lng.into(),
+            lat: lat.into(),
+            combined: None,
+            system: None,
+        }
+    }
+
+    /// Replace the `combined` result with `lng` and `lat` results, left empty when it
+    /// doesn't hold a point
+    fn split(&self, results: &mut serde_json::Value) {
+        let Some(combined) = &self.combined else {
+            return;
+        };
+        let Some(results) = results.as_object_mut() else {
+            return;
+        };
+
+        let point = results
+            .remove(combined)
+            .and_then(|v| {
+                let (lng, lat) = v.as_str()?.split_once(',')?;
+                Some((
+                    lng.trim().parse::<f64>().ok()?,
+                    lat.trim().parse::<f64>().ok()?,
+                ))
+            })
+            .unzip();
+        results.insert(self.lng.clone(), json!(point.0));
+        results.insert(self.lat.clone(), json!(point.1));
+    }
+
+    pub fn with_system(mut self, system: CoordSystem) -> Self {
+        self.system = Some(system);
+        self
+    }
+}
+
+impl SuccessProfile {
+    pub fn new(path: impl Into<String>, equals: serde_json::Value) -> Self {
+        Self {
+            path: path.into(),
+            equals,
+            message_path: None,
+            errors: HashMap::new(),
+        }
+    }
+
+    pub fn check(&self, body: &serde_json::Value) -> Result<(), ApiError> {
+        let status = get!(body.clone(), serde_json::json!(self.path));
+        if loosely_equals(&status, &self.equals) {
+            return Ok(());
+        }
+
+        let mut paths = self
+            .message_path
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        // Baidu answers most errors with a `message`, but some with a `msg`
+        if self.errors.is_empty() {
+            paths.push("msg");
+        }
+        let message = paths
+            .into_iter()
+            .map(|path| get!(body.clone(), serde_json::json!(path)))
+            .find_map(|v| match v {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(s) => Some(s),
+                v => Some(v.to_string()),
+            });
+
+        let kind = self.classify(&status, message.as_deref());
+        Err(ApiError::new(kind, status, message))
+    }
+
+    fn classify(&self, status: &serde_json::Value, message: Option<&str>) -> ApiErrorKind {
+        if self.errors.is_empty() {
+            return status
+                .as_i64()
+                .or_else(|| status.as_str().and_then(|s| s.parse().ok()))
+                .map_or(ApiErrorKind::Unknown, |status| {
+                    ApiErrorKind::from_baidu_response(status, message)
+                });
+        }
+
+        self.errors
+            .iter()
+            .find(|(_, codes)| codes.iter().any(|code| loosely_equals(status, code)))
+            .map_or(ApiErrorKind::Unknown, |(kind, _)| *kind)
+    }
+}
+
+/// Providers are not consistent about returning codes as numbers or strings,
+/// so `"0"` and `0` are considered equal.
+pub(crate) fn loosely_equals(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    match (a, b) {
+        (serde_json::Value::String(s), v) | (v, serde_json::Value::String(s)) if !v.is_string() => {
+            s == &v.to_string()
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn check_success_should_work() {
+        let mut success = SuccessProfile::new("status", json!(0));
+        success.message_path = Some("message".into());
+
+        assert!(success.check(&json!({"status": 0, "result": {}})).is_ok());
+        assert!(success.check(&json!({"status": "0"})).is_ok());
+
+        let err = success
+            .check(&json!({"status": 302, "message": "天配额超限"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::QuotaExceeded);
+        assert_eq!(err.status, json!(302));
+        assert_eq!(err.message.as_deref(), Some("天配额超限"));
+
+        // Baidu can't resolve the address
+        let body = json!({"status": 1, "msg": "Internal Service Error:无相关结果", "results": []});
+        let err = success.check(&body).unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::NoResult);
+        assert_eq!(
+            err.message.as_deref(),
+            Some("Internal Service Error:无相关结果")
+        );
+
+        let err = success.check(&json!({"status": 1})).unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::Unknown);
+    }
+
+    #[test]
+    fn check_success_with_custom_errors_should_work() {
+        let profile: ResponseProfile = serde_yaml::from_str(
+            r#"
+            success:
+              path: infocode
+              equals: "10000"
+              errors:
+                invalid_key: ["10001"]
+                quota_exceeded: ["10003", "10044"]
+            "#,
+        )
+        .unwrap();
+
+        let err = profile
+            .check_success(&json!({"infocode": "10044"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::QuotaExceeded);
+
+        let err = profile
+            .check_success(&json!({"infocode": "20000"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::Unknown);
+    }
+
+    #[test]
+    fn combined_coordinates_should_be_split() {
+        let profile: ResponseProfile = serde_yaml::from_str(
+            r#"
+            pick_results:
+              geocodes.0.location: '坐标'
+              geocodes.0.level: '地址结构'
+            coordinates:
+              combined: '坐标'
+              lng: '经度坐标'
+              lat: '纬度坐标'
+              system: gcj02
+            "#,
+        )
+        .unwrap();
+
+        let body = json!({"geocodes": [{"location": "104.138771,36.545047", "level": "区县"}]});
+        assert_eq!(
+            profile.results(&body).unwrap(),
+            json!({"经度坐标": 104.138771, "纬度坐标": 36.545047, "地址结构": "区县"})
+        );
+
+        let body = json!({"geocodes": [{"location": [], "level": "区县"}]});
+        assert_eq!(
+            profile.results(&body).unwrap(),
+            json!({"经度坐标": null, "纬度坐标": null, "地址结构": "区县"})
+        );
     }
 }
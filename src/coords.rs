@@ -0,0 +1,401 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// The coordinate systems Chinese map providers answer in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordSystem {
+    /// GPS longitude and latitude
+    #[serde(alias = "wgs84ll")]
+    #[value(alias = "wgs84ll")]
+    Wgs84,
+    /// The obfuscated system mandated in China, used by AMap and Tencent
+    #[serde(alias = "gcj02ll")]
+    #[value(alias = "gcj02ll")]
+    Gcj02,
+    /// Baidu longitude and latitude
+    Bd09ll,
+    /// Baidu mercator metres
+    Bd09mc,
+}
+
+/// Semi-major axis of the Krasovsky 1940 ellipsoid GCJ-02 is based on
+const A: f64 = 6378245.0;
+/// Eccentricity squared of the Krasovsky 1940 ellipsoid
+const EE: f64 = 0.006_693_421_622_965_943;
+const X_PI: f64 = PI * 3000.0 / 180.0;
+
+/// Inverse transforms stop once the forward transform lands this close, in degrees
+const PRECISION: f64 = 1e-10;
+const MAX_ITERATIONS: usize = 30;
+
+const MC_BAND: [f64; 6] = [
+    12890594.86,
+    8362377.87,
+    5591021.0,
+    3481989.83,
+    1678043.12,
+    0.0,
+];
+const LL_BAND: [f64; 6] = [75.0, 60.0, 45.0, 30.0, 15.0, 0.0];
+
+#[allow(clippy::excessive_precision)]
+const MC_TO_LL: [[f64; 10]; 6] = [
+    [
+        1.410526172116255e-8,
+        0.00000898305509648872,
+        -1.9939833816331,
+        200.9824383106796,
+        -187.2403703815547,
+        91.6087516669843,
+        -23.38765649603339,
+        2.57121317296198,
+        -0.03801003308653,
+        17337981.2,
+    ],
+    [
+        -7.435856389565537e-9,
+        0.000008983055097726239,
+        -0.78625201886289,
+        96.32687599759846,
+        -1.85204757529826,
+        -59.36935905485877,
+        47.40033549296737,
+        -16.50741931063887,
+        2.28786674699375,
+        10260144.86,
+    ],
+    [
+        -3.030883460898826e-8,
+        0.00000898305509983578,
+        0.30071316287616,
+        59.74293618442277,
+        7.357984074871,
+        -25.38371002664745,
+        13.45380521110908,
+        -3.29883767235584,
+        0.32710905363475,
+        6856817.37,
+    ],
+    [
+        -1.981981304930552e-8,
+        0.000008983055099779535,
+        0.03278182852591,
+        40.31678527705744,
+        0.65659298677277,
+        -4.44255534477492,
+        0.85341911805263,
+        0.12923347998204,
+        -0.04625736007561,
+        4482777.06,
+    ],
+    [
+        3.09191371068437e-9,
+        0.000008983055096812155,
+        0.00006995724062,
+        23.10934304144901,
+        -0.00023663490511,
+        -0.6321817810242,
+        -0.00663494467273,
+        0.03430082397953,
+        -0.00466043876332,
+        2555164.4,
+    ],
+    [
+        2.890871144776878e-9,
+        0.000008983055095805407,
+        -3.068298e-8,
+        7.47137025468032,
+        -0.00000353937994,
+        -0.02145144861037,
+        -0.00001234426596,
+        0.00010322952773,
+        -0.00000323890364,
+        826088.5,
+    ],
+];
+
+#[allow(clippy::excessive_precision)]
+const LL_TO_MC: [[f64; 10]; 6] = [
+    [
+        -0.0015702102444,
+        111320.7020616939,
+        1704480524535203.0,
+        -10338987376042340.0,
+        26112667856603880.0,
+        -35149669176653700.0,
+        26595700718403920.0,
+        -10725012454188240.0,
+        1800819912950474.0,
+        82.5,
+    ],
+    [
+        0.0008277824516172526,
+        111320.7020463578,
+        647795574.6671607,
+        -4082003173.641316,
+        10774905663.51142,
+        -15171875531.51559,
+        12053065338.62167,
+        -5124939663.577472,
+        913311935.9512032,
+        67.5,
+    ],
+    [
+        0.00337398766765,
+        111320.7020202162,
+        4481351.045890365,
+        -23393751.19931662,
+        79682215.47186455,
+        -115964993.2797253,
+        97236711.15602145,
+        -43661946.33752821,
+        8477230.501135234,
+        52.5,
+    ],
+    [
+        0.00220636496208,
+        111320.7020209128,
+        51751.86112841131,
+        3796837.749470245,
+        992013.7397791013,
+        -1221952.21711287,
+        1340652.697009075,
+        -620943.6990984312,
+        144416.9293806241,
+        37.5,
+    ],
+    [
+        -0.0003441963504368392,
+        111320.7020576856,
+        278.2353980772752,
+        2485758.690035394,
+        6070.750963243378,
+        54821.18345352118,
+        9540.606633304236,
+        -2710.55326746645,
+        1405.483844121726,
+        22.5,
+    ],
+    [
+        -0.0003218135878613132,
+        111320.7020701615,
+        0.00369383431289,
+        823725.6402795718,
+        0.46104986909093,
+        2351.343141331292,
+        1.58060784298199,
+        8.77738589078284,
+        0.37238884252424,
+        7.45,
+    ],
+];
+
+impl CoordSystem {
+    /// Convert a longitude and latitude, or mercator x and y, from this system into `to`
+    pub fn convert(self, lng: f64, lat: f64, to: CoordSystem) -> (f64, f64) {
+        if self == to {
+            return (lng, lat);
+        }
+
+        // everything goes through gcj02, which sits between wgs84 and the baidu systems
+        let (lng, lat) = match self {
+            CoordSystem::Wgs84 => wgs84_to_gcj02(lng, lat),
+            CoordSystem::Gcj02 => (lng, lat),
+            CoordSystem::Bd09ll => bd09ll_to_gcj02(lng, lat),
+            CoordSystem::Bd09mc => {
+                let (lng, lat) = bd09mc_to_bd09ll(lng, lat);
+                bd09ll_to_gcj02(lng, lat)
+            }
+        };
+
+        match to {
+            CoordSystem::Wgs84 => gcj02_to_wgs84(lng, lat),
+            CoordSystem::Gcj02 => (lng, lat),
+            CoordSystem::Bd09ll => gcj02_to_bd09ll(lng, lat),
+            CoordSystem::Bd09mc => {
+                let (lng, lat) = gcj02_to_bd09ll(lng, lat);
+                bd09ll_to_bd09mc(lng, lat)
+            }
+        }
+    }
+}
+
+/// GCJ-02 only obfuscates coordinates inside China, everywhere else it equals WGS84
+pub fn out_of_china(lng: f64, lat: f64) -> bool {
+    !(72.004..=137.8347).contains(&lng) || !(0.8293..=55.8271).contains(&lat)
+}
+
+pub fn wgs84_to_gcj02(lng: f64, lat: f64) -> (f64, f64) {
+    if out_of_china(lng, lat) {
+        return (lng, lat);
+    }
+
+    let (dlng, dlat) = gcj02_offset(lng, lat);
+    (lng + dlng, lat + dlat)
+}
+
+/// The inverse of `wgs84_to_gcj02`, refined until it round trips
+pub fn gcj02_to_wgs84(lng: f64, lat: f64) -> (f64, f64) {
+    if out_of_china(lng, lat) {
+        return (lng, lat);
+    }
+
+    invert(lng, lat, wgs84_to_gcj02)
+}
+
+pub fn gcj02_to_bd09ll(lng: f64, lat: f64) -> (f64, f64) {
+    let z = (lng * lng + lat * lat).sqrt() + 0.00002 * (lat * X_PI).sin();
+    let theta = lat.atan2(lng) + 0.000003 * (lng * X_PI).cos();
+    (z * theta.cos() + 0.0065, z * theta.sin() + 0.006)
+}
+
+/// The inverse of `gcj02_to_bd09ll`, refined until it round trips
+pub fn bd09ll_to_gcj02(lng: f64, lat: f64) -> (f64, f64) {
+    invert(lng, lat, gcj02_to_bd09ll)
+}
+
+pub fn bd09ll_to_bd09mc(lng: f64, lat: f64) -> (f64, f64) {
+    // the mercator projection baidu uses is only defined up to ±74°
+    let lng = (lng + 180.0).rem_euclid(360.0) - 180.0;
+    let lat = lat.clamp(-74.0, 74.0);
+
+    let band = LL_BAND
+        .iter()
+        .position(|&band| lat.abs() >= band)
+        .unwrap_or(LL_BAND.len() - 1);
+    mercator(lng, lat, &LL_TO_MC[band])
+}
+
+pub fn bd09mc_to_bd09ll(x: f64, y: f64) -> (f64, f64) {
+    let band = MC_BAND
+        .iter()
+        .position(|&band| y.abs() >= band)
+        .unwrap_or(MC_BAND.len() - 1);
+    mercator(x, y, &MC_TO_LL[band])
+}
+
+/// Baidu's piecewise polynomial approximation, used both ways with different factors
+fn mercator(x: f64, y: f64, factors: &[f64; 10]) -> (f64, f64) {
+    let cc = y.abs() / factors[9];
+    let out_x = factors[0] + factors[1] * x.abs();
+    let out_y = factors[2..9]
+        .iter()
+        .rev()
+        .fold(0.0, |acc, &factor| acc * cc + factor);
+
+    (out_x.copysign(x), out_y.copysign(y))
+}
+
+fn gcj02_offset(lng: f64, lat: f64) -> (f64, f64) {
+    let (x, y) = (lng - 105.0, lat - 35.0);
+
+    let mut dlat = -100.0 + 2.0 * x + 3.0 * y + 0.2 * y * y + 0.1 * x * y + 0.2 * x.abs().sqrt();
+    dlat += (20.0 * (6.0 * x * PI).sin() + 20.0 * (2.0 * x * PI).sin()) * 2.0 / 3.0;
+    dlat += (20.0 * (y * PI).sin() + 40.0 * (y / 3.0 * PI).sin()) * 2.0 / 3.0;
+    dlat += (160.0 * (y / 12.0 * PI).sin() + 320.0 * (y * PI / 30.0).sin()) * 2.0 / 3.0;
+
+    let mut dlng = 300.0 + x + 2.0 * y + 0.1 * x * x + 0.1 * x * y + 0.1 * x.abs().sqrt();
+    dlng += (20.0 * (6.0 * x * PI).sin() + 20.0 * (2.0 * x * PI).sin()) * 2.0 / 3.0;
+    dlng += (20.0 * (x * PI).sin() + 40.0 * (x / 3.0 * PI).sin()) * 2.0 / 3.0;
+    dlng += (150.0 * (x / 12.0 * PI).sin() + 300.0 * (x / 30.0 * PI).sin()) * 2.0 / 3.0;
+
+    let rad_lat = lat / 180.0 * PI;
+    let magic = 1.0 - EE * rad_lat.sin().powi(2);
+    let sqrt_magic = magic.sqrt();
+    dlat = (dlat * 180.0) / ((A * (1.0 - EE)) / (magic * sqrt_magic) * PI);
+    dlng = (dlng * 180.0) / (A / sqrt_magic * rad_lat.cos() * PI);
+
+    (dlng, dlat)
+}
+
+/// Find the point `forward` maps onto (lng, lat). Both offsets are small and smooth,
+/// so stepping back by the remaining error converges in a handful of iterations.
+fn invert(lng: f64, lat: f64, forward: fn(f64, f64) -> (f64, f64)) -> (f64, f64) {
+    let (mut x, mut y) = (lng, lat);
+    for _ in 0..MAX_ITERATIONS {
+        let (fx, fy) = forward(x, y);
+        let (dx, dy) = (fx - lng, fy - lat);
+        if dx.abs() < PRECISION && dy.abs() < PRECISION {
+            break;
+        }
+        x -= dx;
+        y -= dy;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} is not within {} of {:?}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn wgs84_to_gcj02_should_work() {
+        let gcj = wgs84_to_gcj02(116.404, 39.915);
+        assert_close(gcj, (116.410_244_499, 39.916_404_281), 1e-8);
+
+        let wgs = gcj02_to_wgs84(gcj.0, gcj.1);
+        assert_close(wgs, (116.404, 39.915), 1e-9);
+    }
+
+    #[test]
+    fn out_of_china_should_pass_through() {
+        let tokyo = (139.691_706, 35.689_487);
+        for to in [CoordSystem::Gcj02, CoordSystem::Wgs84] {
+            assert_eq!(CoordSystem::Wgs84.convert(tokyo.0, tokyo.1, to), tokyo);
+            assert_eq!(CoordSystem::Gcj02.convert(tokyo.0, tokyo.1, to), tokyo);
+        }
+    }
+
+    #[test]
+    fn inverse_transforms_should_round_trip() {
+        let systems = [
+            CoordSystem::Wgs84,
+            CoordSystem::Gcj02,
+            CoordSystem::Bd09ll,
+            CoordSystem::Bd09mc,
+        ];
+        let baiyin = (104.144_936, 36.551_072);
+
+        for from in systems {
+            let point = CoordSystem::Gcj02.convert(baiyin.0, baiyin.1, from);
+            for to in systems {
+                let there = from.convert(point.0, point.1, to);
+                let back = to.convert(there.0, there.1, from);
+                // baidu's two mercator polynomials are fitted separately and differ by centimetres
+                let tolerance = if from == CoordSystem::Bd09mc {
+                    0.1
+                } else {
+                    1e-7
+                };
+                assert_close(back, point, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn bd09_should_work() {
+        let bd = gcj02_to_bd09ll(116.404, 39.915);
+        assert_close(bd, (116.410_369_493, 39.921_336_993), 1e-8);
+
+        // the inverse iterates until the forward transform lands within `PRECISION`
+        let gcj = bd09ll_to_gcj02(116.404, 39.915);
+        assert_close(gcj, (116.397_627_087_859, 39.908_656_339_457), 1e-9);
+        assert_close(gcj02_to_bd09ll(gcj.0, gcj.1), (116.404, 39.915), PRECISION);
+
+        // Tiananmen in baidu mercator
+        let mc = bd09ll_to_bd09mc(116.403_874, 39.914_888);
+        assert_close(mc, (12_958_160.97, 4_825_907.72), 1.0);
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+/// A single difference between two JSON values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffEntry {
+    /// Only present on the left side
+    Removed(String, Value),
+    /// Only present on the right side
+    Added(String, Value),
+    /// Present on both sides with different values
+    Changed(String, Value, Value),
+}
+
+/// Structurally diff two JSON values, returning the differences ordered by path.
+///
+/// Objects are compared key by key and arrays index by index, anything else is compared as a whole.
+pub fn diff_json(left: &Value, right: &Value) -> Vec<DiffEntry> {
+    let mut entries = vec![];
+    diff_at("", left, right, &mut entries);
+    entries
+}
+
+impl DiffEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Removed(path, _) | Self::Added(path, _) | Self::Changed(path, _, _) => path,
+        }
+    }
+}
+
+fn diff_at(path: &str, left: &Value, right: &Value, entries: &mut Vec<DiffEntry>) {
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            let mut keys = l.keys().chain(r.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let path = join_path(path, key);
+                match (l.get(key), r.get(key)) {
+                    (Some(l), Some(r)) => diff_at(&path, l, r, entries),
+                    (Some(l), None) => entries.push(DiffEntry::Removed(path, l.clone())),
+                    (None, Some(r)) => entries.push(DiffEntry::Added(path, r.clone())),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for i in 0..l.len().max(r.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (l.get(i), r.get(i)) {
+                    (Some(l), Some(r)) => diff_at(&path, l, r, entries),
+                    (Some(l), None) => entries.push(DiffEntry::Removed(path, l.clone())),
+                    (None, Some(r)) => entries.push(DiffEntry::Added(path, r.clone())),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (l, r) if l != r => entries.push(DiffEntry::Changed(path.into(), l.clone(), r.clone())),
+        _ => {}
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_json_should_work() {
+        let left = json!({
+            "status": 0,
+            "result": {"location": {"lng": 116.3, "lat": 40.0}, "level": "门址"},
+            "tags": ["a", "b"],
+        });
+        let right = json!({
+            "status": 0,
+            "result": {"location": {"lng": 116.3, "lat": 40.1}, "confidence": 80},
+            "tags": ["a"],
+        });
+
+        assert_eq!(
+            diff_json(&left, &right),
+            vec![
+                DiffEntry::Added("result.confidence".into(), json!(80)),
+                DiffEntry::Removed("result.level".into(), json!("门址")),
+                DiffEntry::Changed("result.location.lat".into(), json!(40.0), json!(40.1)),
+                DiffEntry::Removed("tags[1]".into(), json!("b")),
+            ]
+        );
+    }
+}
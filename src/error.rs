@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::OutOfRegion;
+
+/// The kind of error a provider reported in its response body.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    QuotaExceeded,
+    InvalidKey,
+    NoResult,
+    BadRequest,
+    Unknown,
+}
+
+/// A typed error for responses rejected by `ResponseProfile::success`.
+///
+/// It is returned wrapped in an `anyhow::Error`, use `downcast_ref::<ApiError>()` to inspect it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub kind: ApiErrorKind,
+    pub status: serde_json::Value,
+    pub message: Option<String>,
+    pub http_status: Option<u16>,
+}
+
+/// A row given up on because the run stopped at a fatal error before the row was done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aborted;
+
+/// A flat description of why a row failed, used by rejects files and reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    /// An `ApiErrorKind` in snake case, or `network`, `timeout`, `invalid_response`,
+    /// `out_of_region`, `aborted`, `other`
+    pub kind: String,
+    pub http_status: Option<u16>,
+    /// The provider's status code, if the provider reported the error
+    pub status: Option<serde_json::Value>,
+    pub message: String,
+}
+
+impl ApiErrorKind {
+    /// Classify a status code according to Baidu's LBS documentation.
+    pub fn from_baidu_status(status: i64) -> Self {
+        match status {
+            2 => Self::BadRequest,
+            3 | 5 | 101 | 102 | 200..=299 => Self::InvalidKey,
+            4 | 300..=399 => Self::QuotaExceeded,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Classify an error response of Baidu's. Status 1 is Baidu's internal error, but it
+    /// is also how an address which can't be resolved is reported, with `无相关结果`.
+    pub fn from_baidu_response(status: i64, message: Option<&str>) -> Self {
+        match status {
+            1 if message.is_some_and(|m| m.contains("无相关结果")) => Self::NoResult,
+            status => Self::from_baidu_status(status),
+        }
+    }
+
+    /// Classify an unsuccessful http status code.
+    pub fn from_http_status(status: u16) -> Self {
+        match status {
+            400 | 422 => Self::BadRequest,
+            401 | 403 => Self::InvalidKey,
+            404 => Self::NoResult,
+            429 => Self::QuotaExceeded,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::InvalidKey => "invalid_key",
+            Self::NoResult => "no_result",
+            Self::BadRequest => "bad_request",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Whether retrying or moving on to the next row is pointless.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::QuotaExceeded | Self::InvalidKey)
+    }
+}
+
+impl ApiError {
+    pub fn new(kind: ApiErrorKind, status: serde_json::Value, message: Option<String>) -> Self {
+        Self {
+            kind,
+            status,
+            message,
+            http_status: None,
+        }
+    }
+
+    pub fn with_http_status(mut self, http_status: u16) -> Self {
+        self.http_status = Some(http_status);
+        self
+    }
+}
+
+impl RowError {
+    pub fn from_error(e: &anyhow::Error) -> Self {
+        if let Some(row_error) = e.downcast_ref::<RowError>() {
+            return row_error.clone();
+        }
+        if let Some(api_error) = e.downcast_ref::<ApiError>() {
+            return Self {
+                kind: api_error.kind.as_str().into(),
+                http_status: api_error.http_status,
+                status: Some(api_error.status.clone()).filter(|s| !s.is_null()),
+                message: api_error.message.clone().unwrap_or_default(),
+            };
+        }
+
+        let kind = if e.downcast_ref::<OutOfRegion>().is_some() {
+            "out_of_region"
+        } else if e.downcast_ref::<Aborted>().is_some() {
+            "aborted"
+        } else if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                "timeout"
+            } else {
+                "network"
+            }
+        } else if e.downcast_ref::<serde_json::Error>().is_some() {
+            "invalid_response"
+        } else {
+            "other"
+        };
+
+        Self {
+            kind: kind.into(),
+            http_status: e
+                .downcast_ref::<reqwest::Error>()
+                .and_then(|e| e.status())
+                .map(|s| s.as_u16()),
+            status: None,
+            message: e.to_string(),
+        }
+    }
+
+    /// Whether the same request may succeed when sent again
+    pub fn is_retryable(e: &anyhow::Error) -> bool {
+        match e.downcast_ref::<ApiError>() {
+            Some(api_error) => api_error.kind == ApiErrorKind::Unknown,
+            None => e.downcast_ref::<reqwest::Error>().is_some(),
+        }
+    }
+}
+
+impl fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::QuotaExceeded => "quota exceeded",
+            Self::InvalidKey => "invalid key",
+            Self::NoResult => "no result",
+            Self::BadRequest => "bad request",
+            Self::Unknown => "unknown error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (status {})", self.kind, self.status)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "aborted after a fatal error")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RowError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn row_error_from_api_error_should_work() {
+        let e: anyhow::Error = ApiError::new(
+            ApiErrorKind::QuotaExceeded,
+            json!(302),
+            Some("天配额超限".into()),
+        )
+        .with_http_status(200)
+        .into();
+        let e = e.context("failed to query 白银市");
+
+        assert_eq!(
+            RowError::from_error(&e),
+            RowError {
+                kind: "quota_exceeded".into(),
+                http_status: Some(200),
+                status: Some(json!(302)),
+                message: "天配额超限".into(),
+            }
+        );
+        assert!(!RowError::is_retryable(&e));
+    }
+
+    #[test]
+    fn row_error_from_aborted_should_work() {
+        let e = anyhow::Error::from(Aborted).context("failed to query 白银市");
+
+        let row_error = RowError::from_error(&e);
+        assert_eq!(row_error.kind, "aborted");
+        assert_eq!(row_error.http_status, None);
+
+        // a row error shared between the rows of a batch keeps its kind
+        let e = anyhow::Error::from(row_error.clone()).context("failed to query 白银市");
+        assert_eq!(RowError::from_error(&e), row_error);
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io::Read, path::Path, sync::OnceLock};
+
+/// The bundled table: every province, prefecture and county-level division of the 2022
+/// GB/T 2260 codes. Load another vintage with `Gazetteer::open` when needed.
+const BUNDLED: &str = include_str!("gb2260.csv");
+
+/// The level of an administrative division
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DivisionLevel {
+    Province,
+    City,
+    County,
+}
+
+/// An administrative division of GB/T 2260, e.g. `620402 白银区`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Division {
+    pub code: u32,
+    pub name: String,
+    /// The closest division above this one which is in the table
+    pub parent: Option<u32>,
+    pub level: DivisionLevel,
+}
+
+/// An offline table of administrative divisions, looked up by code or by name
+#[derive(Debug, Default)]
+pub struct Gazetteer {
+    divisions: HashMap<u32, Division>,
+    by_name: HashMap<String, Vec<u32>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    code: u32,
+    name: String,
+    #[serde(default)]
+    parent: Option<u32>,
+    #[serde(default)]
+    level: Option<DivisionLevel>,
+}
+
+impl DivisionLevel {
+    /// The level a code has when the table doesn't say
+    fn of(code: u32) -> Self {
+        match (code % 10_000, code % 100) {
+            (0, _) => Self::Province,
+            (_, 0) => Self::City,
+            _ => Self::County,
+        }
+    }
+}
+
+impl Gazetteer {
+    /// The table bundled with the library
+    pub fn bundled() -> &'static Self {
+        static BUNDLED_GAZETTEER: OnceLock<Gazetteer> = OnceLock::new();
+        BUNDLED_GAZETTEER
+            .get_or_init(|| Self::from_reader(BUNDLED.as_bytes()).expect("a valid bundled table"))
+    }
+
+    /// Load a table from a CSV file with `code` and `name` columns, and optionally `parent`
+    /// and `level` columns. Without them both are worked out from the code.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("failed to open gazetteer {}", path.display()))?;
+        Self::from_reader(file).with_context(|| format!("invalid gazetteer {}", path.display()))
+    }
+
+    pub fn from_reader(reader: impl Read) -> Result<Self> {
+        let mut entries = HashMap::new();
+        for entry in csv::Reader::from_reader(reader).deserialize() {
+            let entry: Entry = entry?;
+            if !(100_000..1_000_000).contains(&entry.code) {
+                return Err(anyhow!("{} is not a six digit division code", entry.code));
+            }
+            entries.insert(entry.code, entry);
+        }
+
+        let mut gazetteer = Self::default();
+        for (&code, entry) in &entries {
+            // a county of a province-administered city hangs from the province
+            let parent = match entry.parent {
+                Some(parent) if !entries.contains_key(&parent) => {
+                    return Err(anyhow!(
+                        "the parent {} of {} is not in the table",
+                        parent,
+                        code
+                    ));
+                }
+                Some(parent) => Some(parent),
+                None => [code / 100 * 100, code / 10_000 * 10_000]
+                    .into_iter()
+                    .find(|&parent| parent != code && entries.contains_key(&parent)),
+            };
+            let division = Division {
+                code,
+                name: entry.name.clone(),
+                parent,
+                level: entry.level.unwrap_or_else(|| DivisionLevel::of(code)),
+            };
+            gazetteer
+                .by_name
+                .entry(entry.name.clone())
+                .or_default()
+                .push(code);
+            gazetteer.divisions.insert(code, division);
+        }
+        for codes in gazetteer.by_name.values_mut() {
+            codes.sort();
+        }
+
+        Ok(gazetteer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.divisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.divisions.is_empty()
+    }
+
+    pub fn get(&self, code: u32) -> Option<&Division> {
+        self.divisions.get(&code)
+    }
+
+    /// Look up an adcode as a provider returns it, a string or a number
+    pub fn get_adcode(&self, adcode: &serde_json::Value) -> Option<&Division> {
+        let code = match adcode {
+            serde_json::Value::String(s) => s.trim().parse().ok()?,
+            adcode => adcode.as_u64()?.try_into().ok()?,
+        };
+        self.get(code)
+    }
+
+    /// Every division with this name
+    pub fn find(&self, name: &str) -> Vec<&Division> {
+        self.by_name
+            .get(name.trim())
+            .into_iter()
+            .flatten()
+            .filter_map(|code| self.get(*code))
+            .collect()
+    }
+
+    /// The only division with this name, narrowed down by a parent region such as
+    /// `甘肃`, `白银市` or `甘肃省白银市` when the name alone is ambiguous
+    pub fn resolve(&self, name: &str, parent: Option<&str>) -> Option<&Division> {
+        let mut found = self.find(name);
+        if let Some(parent) = parent.map(str::trim).filter(|p| !p.is_empty()) {
+            found.retain(|division| {
+                self.ancestors(division)
+                    .iter()
+                    .any(|a| parent.contains(&a.name) || a.name.starts_with(parent))
+            });
+        }
+
+        match found[..] {
+            [division] => Some(division),
+            _ => None,
+        }
+    }
+
+    /// The divisions above a division, the province first
+    pub fn ancestors(&self, division: &Division) -> Vec<&Division> {
+        let mut ancestors = vec![];
+        let mut parent = division.parent;
+        while let Some(division) = parent.and_then(|code| self.get(code)) {
+            ancestors.push(division);
+            parent = division.parent;
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    /// A division with the divisions above it, the province first
+    pub fn hierarchy<'a>(&'a self, division: &'a Division) -> Vec<&'a Division> {
+        let mut hierarchy = self.ancestors(division);
+        hierarchy.push(division);
+        hierarchy
+    }
+
+    /// Whether `code` is `ancestor` or lies within it
+    pub fn contains(&self, ancestor: u32, code: u32) -> bool {
+        let mut code = Some(code);
+        while let Some(c) = code {
+            if c == ancestor {
+                return true;
+            }
+            code = self.get(c).and_then(|division| division.parent);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn bundled_table_should_load() {
+        let gazetteer = Gazetteer::bundled();
+        assert!(!gazetteer.is_empty());
+
+        let division = gazetteer.get(620402).unwrap();
+        assert_eq!(division.name, "白银区");
+        assert_eq!(division.level, DivisionLevel::County);
+        let names = gazetteer
+            .hierarchy(division)
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["甘肃省", "白银市", "白银区"]);
+
+        // 济源 is administered by the province directly
+        let jiyuan = gazetteer.find("济源市");
+        assert_eq!(jiyuan[0].parent, Some(410000));
+        assert_eq!(jiyuan[0].level, DivisionLevel::County);
+
+        let count = |level| {
+            gazetteer
+                .divisions
+                .values()
+                .filter(|d| d.level == level)
+                .count()
+        };
+        assert_eq!(count(DivisionLevel::Province), 34);
+        assert_eq!(count(DivisionLevel::City), 333);
+        assert_eq!(count(DivisionLevel::County), 2843);
+
+        // codes abolished before 2022 are gone
+        assert!(gazetteer.get(610322).is_none());
+        assert_eq!(gazetteer.get(610305).unwrap().name, "凤翔区");
+    }
+
+    #[test]
+    fn tables_without_parent_and_level_should_load() {
+        let csv = "code,name\n410000,河南省\n410300,洛阳市\n410308,孟津区\n419001,济源市\n";
+        let gazetteer = Gazetteer::from_reader(csv.as_bytes()).unwrap();
+
+        assert_eq!(gazetteer.get(410308).unwrap().parent, Some(410300));
+        assert_eq!(gazetteer.get(419001).unwrap().parent, Some(410000));
+        assert_eq!(gazetteer.get(410300).unwrap().level, DivisionLevel::City);
+
+        let csv = "code,name,parent,level\n410308,孟津区,410300,county\n";
+        assert!(Gazetteer::from_reader(csv.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn resolve_should_narrow_ambiguous_names() {
+        let gazetteer = Gazetteer::bundled();
+        assert_eq!(gazetteer.find("城区").len(), 3);
+        assert_eq!(gazetteer.resolve("城区", None), None);
+        assert_eq!(
+            gazetteer.resolve("城区", Some("晋城")).unwrap().code,
+            140502
+        );
+        assert_eq!(
+            gazetteer
+                .resolve("新城区", Some("陕西省西安市"))
+                .unwrap()
+                .code,
+            610102
+        );
+        assert_eq!(gazetteer.resolve("会宁县", None).unwrap().code, 620422);
+        assert_eq!(gazetteer.resolve("会宁县", Some("河南")), None);
+    }
+
+    #[test]
+    fn adcodes_should_be_checked() {
+        let gazetteer = Gazetteer::bundled();
+        assert!(gazetteer.contains(620400, 620422));
+        assert!(gazetteer.contains(620000, 620422));
+        assert!(!gazetteer.contains(410000, 620422));
+
+        assert_eq!(
+            gazetteer.get_adcode(&json!("620422")).unwrap().name,
+            "会宁县"
+        );
+        assert_eq!(gazetteer.get_adcode(&json!(620400)).unwrap().name, "白银市");
+        assert!(gazetteer.get_adcode(&json!("[]")).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{header::HeaderMap, Method, Url};
+use serde_json::{json, Value};
+
+use super::{geocode_with, no_result, text, GeocodeResult, Geocoder};
+use crate::{
+    ApiErrorKind, CoordSystem, ExtraArgs, RequestProfile, ResponseProfile, SuccessProfile,
+};
+
+/// AMap's (Gaode) geocoding v3 API, answering in gcj02.
+#[derive(Debug, Clone)]
+pub struct AmapGeocoder {
+    pub req: RequestProfile,
+    res: ResponseProfile,
+}
+
+impl AmapGeocoder {
+    pub const URL: &'static str = "https://restapi.amap.com/v3/geocode/geo";
+
+    pub fn new(key: impl Into<String>) -> Self {
+        let params = json!({
+            "key": key.into(),
+            "output": "JSON",
+        });
+        let url = Url::parse(Self::URL).expect("a valid url");
+
+        Self::from_profile(RequestProfile::new(
+            Method::GET,
+            url,
+            Some(params),
+            HeaderMap::new(),
+            None,
+        ))
+    }
+
+    /// Use a request profile of your own, e.g. to point at another host
+    pub fn from_profile(req: RequestProfile) -> Self {
+        let mut success = SuccessProfile::new("infocode", json!("10000"));
+        success.message_path = Some("info".into());
+        success.errors = [
+            (
+                ApiErrorKind::InvalidKey,
+                vec![json!("10001"), json!("10009")],
+            ),
+            (
+                ApiErrorKind::QuotaExceeded,
+                vec![json!("10003"), json!("10004"), json!("10044")],
+            ),
+            (
+                ApiErrorKind::BadRequest,
+                vec![json!("20000"), json!("20001")],
+            ),
+        ]
+        .into();
+
+        let res = ResponseProfile {
+            success: Some(success),
+            ..Default::default()
+        };
+        Self { req, res }
+    }
+
+    pub fn parse(&self, body: &Value) -> Result<GeocodeResult> {
+        let geocode = &body["geocodes"][0];
+
+        // the location is a single "lng,lat" string
+        let location = geocode["location"].as_str().and_then(|location| {
+            let (lng, lat) = location.split_once(',')?;
+            Some((lng.trim().parse().ok()?, lat.trim().parse().ok()?))
+        });
+        let Some((lng, lat)) = location else {
+            return Err(no_result());
+        };
+
+        Ok(GeocodeResult {
+            lng,
+            lat,
+            system: CoordSystem::Gcj02,
+            level: text(&geocode["level"]),
+            confidence: None,
+            formatted_address: text(&geocode["formatted_address"]),
+            adcode: text(&geocode["adcode"]),
+            raw: body.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for AmapGeocoder {
+    fn name(&self) -> &str {
+        "amap"
+    }
+
+    async fn geocode(&self, address: &str, args: &ExtraArgs) -> Result<GeocodeResult> {
+        let parse = |body: &Value| self.parse(body);
+        geocode_with(&self.req, &self.res, "address", address, args, parse).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{geocoder::fixture, ApiError};
+
+    #[test]
+    fn parse_should_work() {
+        let geocoder = AmapGeocoder::new("key");
+        let body = fixture("amap.json");
+        geocoder.res.check_success(&body).unwrap();
+
+        let result = geocoder.parse(&body).unwrap();
+        assert_eq!((result.lng, result.lat), (104.138_771, 36.545_047));
+        assert_eq!(result.system, CoordSystem::Gcj02);
+        assert_eq!(result.level.as_deref(), Some("区县"));
+        assert_eq!(
+            result.formatted_address.as_deref(),
+            Some("甘肃省白银市白银区")
+        );
+        assert_eq!(result.adcode.as_deref(), Some("620402"));
+    }
+
+    #[test]
+    fn errors_should_be_classified() {
+        let geocoder = AmapGeocoder::new("key");
+        let err = geocoder
+            .res
+            .check_success(&json!({"status": "0", "info": "INVALID_USER_KEY", "infocode": "10001"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::InvalidKey);
+        assert_eq!(err.message.as_deref(), Some("INVALID_USER_KEY"));
+
+        let body = json!({"status": "1", "infocode": "10000", "count": "0", "geocodes": []});
+        let err = geocoder.parse(&body).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>().unwrap().kind,
+            ApiErrorKind::NoResult
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{header::HeaderMap, Method, Url};
+use serde_json::{json, Value};
+
+use super::{geocode_with, no_result, number, text, GeocodeResult, Geocoder};
+use crate::{CoordSystem, ExtraArgs, RequestProfile, ResponseProfile, SuccessProfile};
+
+/// Baidu's geocoding v3 API, answering in `ret_coordtype` or bd09ll when it is not set.
+#[derive(Debug, Clone)]
+pub struct BaiduGeocoder {
+    pub req: RequestProfile,
+    res: ResponseProfile,
+}
+
+impl BaiduGeocoder {
+    pub const URL: &'static str = "https://api.map.baidu.com/geocoding/v3/";
+
+    pub fn new(ak: impl Into<String>) -> Self {
+        let params = json!({
+            "ak": ak.into(),
+            "output": "json",
+            "ret_coordtype": "gcj02ll",
+        });
+        let url = Url::parse(Self::URL).expect("a valid url");
+
+        Self::from_profile(RequestProfile::new(
+            Method::GET,
+            url,
+            Some(params),
+            HeaderMap::new(),
+            None,
+        ))
+    }
+
+    /// Use a request profile of your own, e.g. to point at another host
+    pub fn from_profile(req: RequestProfile) -> Self {
+        let mut success = SuccessProfile::new("status", json!(0));
+        success.message_path = Some("message".into());
+
+        let res = ResponseProfile {
+            success: Some(success),
+            ..Default::default()
+        };
+        Self { req, res }
+    }
+
+    fn system(&self) -> CoordSystem {
+        self.req
+            .params
+            .as_ref()
+            .and_then(|params| params.get("ret_coordtype"))
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or(CoordSystem::Bd09ll)
+    }
+
+    pub fn parse(&self, body: &Value) -> Result<GeocodeResult> {
+        let result = &body["result"];
+        let (Some(lng), Some(lat)) = (
+            number(&result["location"]["lng"]),
+            number(&result["location"]["lat"]),
+        ) else {
+            return Err(no_result());
+        };
+
+        Ok(GeocodeResult {
+            lng,
+            lat,
+            system: self.system(),
+            level: text(&result["level"]),
+            confidence: number(&result["confidence"]),
+            formatted_address: None,
+            adcode: None,
+            raw: body.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for BaiduGeocoder {
+    fn name(&self) -> &str {
+        "baidu"
+    }
+
+    async fn geocode(&self, address: &str, args: &ExtraArgs) -> Result<GeocodeResult> {
+        let parse = |body: &Value| self.parse(body);
+        geocode_with(&self.req, &self.res, "address", address, args, parse).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{geocoder::fixture, ApiError, ApiErrorKind, Replay};
+    use std::sync::Arc;
+
+    #[test]
+    fn parse_should_work() {
+        let geocoder = BaiduGeocoder::new("ak");
+        let body = fixture("baidu.json");
+        geocoder.res.check_success(&body).unwrap();
+
+        let result = geocoder.parse(&body).unwrap();
+        assert_eq!(
+            (result.lng, result.lat),
+            (104.144_936_180_712_87, 36.551_071_573_938_55)
+        );
+        assert_eq!(result.system, CoordSystem::Gcj02);
+        assert_eq!(result.level.as_deref(), Some("政府机构"));
+        assert_eq!(result.confidence, Some(80.0));
+    }
+
+    #[test]
+    fn errors_should_be_classified() {
+        let geocoder = BaiduGeocoder::new("ak");
+        let err = geocoder
+            .res
+            .check_success(&json!({"status": 302, "message": "天配额超限，限制访问"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::QuotaExceeded);
+
+        let err = geocoder
+            .parse(&json!({"status": 0, "result": []}))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>().unwrap().kind,
+            ApiErrorKind::NoResult
+        );
+    }
+
+    #[tokio::test]
+    async fn geocode_should_work_through_the_trait() {
+        let params = json!({"output": "json", "ret_coordtype": "gcj02ll"});
+        let url = Url::parse(BaiduGeocoder::URL).unwrap();
+        let mut req = RequestProfile::new(Method::GET, url, Some(params), HeaderMap::new(), None);
+        req.replay = Some(Arc::new(Replay::open("fixtures/geo_coding.jsonl").unwrap()));
+
+        let geocoder: Box<dyn Geocoder> = Box::new(BaiduGeocoder::from_profile(req));
+        let args = ExtraArgs::default();
+
+        let result = geocoder.geocode("白银市人民政府", &args).await.unwrap();
+        assert_eq!(result.level.as_deref(), Some("政府机构"));
+
+        let err = geocoder.geocode("定西市人民政府", &args).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>().unwrap().kind,
+            ApiErrorKind::QuotaExceeded
+        );
+    }
+}
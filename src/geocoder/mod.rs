@@ -0,0 +1,94 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    output::value_to_f64, ApiError, ApiErrorKind, CoordSystem, ExtraArgs, RequestProfile,
+    ResponseProfile,
+};
+
+mod amap;
+mod baidu;
+mod nominatim;
+mod tencent;
+
+pub use amap::AmapGeocoder;
+pub use baidu::BaiduGeocoder;
+pub use nominatim::NominatimGeocoder;
+pub use tencent::TencentGeocoder;
+
+/// A geocoding provider, answering with the same normalized result whatever
+/// shape its JSON comes in.
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    /// The provider's name, e.g. `baidu`
+    fn name(&self) -> &str;
+
+    /// Geocode an address. `args` are merged into the request like for any profile.
+    async fn geocode(&self, address: &str, args: &ExtraArgs) -> Result<GeocodeResult>;
+}
+
+/// A provider independent geocoding result.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GeocodeResult {
+    pub lng: f64,
+    pub lat: f64,
+
+    /// The coordinate system `lng` and `lat` are in
+    pub system: CoordSystem,
+
+    /// The precision level in the provider's own words, e.g. 门址 or `city`
+    pub level: Option<String>,
+
+    /// How sure the provider is about the result, from 0 to 100
+    pub confidence: Option<f64>,
+
+    pub formatted_address: Option<String>,
+
+    /// The GB/T 2260 administrative division code
+    pub adcode: Option<String>,
+
+    /// The provider's response body
+    pub raw: Value,
+}
+
+/// Send `address` in the `param` query param and parse the checked response body
+async fn geocode_with(
+    req: &RequestProfile,
+    res: &ResponseProfile,
+    param: &str,
+    address: &str,
+    args: &ExtraArgs,
+    parse: impl FnOnce(&Value) -> Result<GeocodeResult>,
+) -> Result<GeocodeResult> {
+    let mut args = args.clone();
+    args.query.push((param.into(), address.into()));
+
+    req.send(&args).await?.parse_with(res, parse).await
+}
+
+/// A number, providers send them as strings just as often
+fn number(v: &Value) -> Option<f64> {
+    value_to_f64(v)
+}
+
+/// A non empty string, AMap sends `[]` for fields it has no value for
+fn text(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn no_result() -> anyhow::Error {
+    ApiError::new(ApiErrorKind::NoResult, json!(null), None).into()
+}
+
+/// Read a recorded response body from `fixtures/geocoder`
+#[cfg(test)]
+fn fixture(name: &str) -> Value {
+    let path = format!("fixtures/geocoder/{}", name);
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
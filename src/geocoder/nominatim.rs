@@ -0,0 +1,108 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, USER_AGENT},
+    Method, Url,
+};
+use serde_json::{json, Value};
+
+use super::{geocode_with, no_result, number, text, GeocodeResult, Geocoder};
+use crate::{CoordSystem, ExtraArgs, RequestProfile, ResponseProfile};
+
+/// OpenStreetMap's Nominatim search API, answering in wgs84. It needs no key but
+/// its usage policy asks for an identifying user agent.
+#[derive(Debug, Clone)]
+pub struct NominatimGeocoder {
+    pub req: RequestProfile,
+    res: ResponseProfile,
+}
+
+impl NominatimGeocoder {
+    pub const URL: &'static str = "https://nominatim.openstreetmap.org/search";
+
+    pub fn new(user_agent: &str) -> Result<Self> {
+        let params = json!({
+            "format": "jsonv2",
+            "limit": 1,
+            "addressdetails": 1,
+        });
+        let url = Url::parse(Self::URL)?;
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(user_agent)?);
+
+        Ok(Self::from_profile(RequestProfile::new(
+            Method::GET,
+            url,
+            Some(params),
+            headers,
+            None,
+        )))
+    }
+
+    /// Use a request profile of your own, e.g. to point at another host
+    pub fn from_profile(req: RequestProfile) -> Self {
+        // errors only come as http status codes
+        Self {
+            req,
+            res: ResponseProfile::default(),
+        }
+    }
+
+    pub fn parse(&self, body: &Value) -> Result<GeocodeResult> {
+        let place = &body[0];
+        let (Some(lng), Some(lat)) = (number(&place["lon"]), number(&place["lat"])) else {
+            return Err(no_result());
+        };
+
+        Ok(GeocodeResult {
+            lng,
+            lat,
+            system: CoordSystem::Wgs84,
+            level: text(&place["addresstype"]),
+            // importance goes from 0 to 1
+            confidence: number(&place["importance"]).map(|i| i * 100.0),
+            formatted_address: text(&place["display_name"]),
+            adcode: None,
+            raw: body.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for NominatimGeocoder {
+    fn name(&self) -> &str {
+        "nominatim"
+    }
+
+    async fn geocode(&self, address: &str, args: &ExtraArgs) -> Result<GeocodeResult> {
+        let parse = |body: &Value| self.parse(body);
+        geocode_with(&self.req, &self.res, "q", address, args, parse).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{geocoder::fixture, ApiError, ApiErrorKind};
+
+    #[test]
+    fn parse_should_work() {
+        let geocoder = NominatimGeocoder::new("poi_cli test").unwrap();
+        let result = geocoder.parse(&fixture("nominatim.json")).unwrap();
+
+        assert_eq!((result.lng, result.lat), (104.138_015_6, 36.545_339_5));
+        assert_eq!(result.system, CoordSystem::Wgs84);
+        assert_eq!(result.level.as_deref(), Some("city"));
+        assert_eq!(result.confidence, Some(52.5));
+        assert_eq!(
+            result.formatted_address.as_deref(),
+            Some("白银区, 白银市, 甘肃省, 中国")
+        );
+
+        let err = geocoder.parse(&json!([])).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>().unwrap().kind,
+            ApiErrorKind::NoResult
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{header::HeaderMap, Method, Url};
+use serde_json::{json, Value};
+
+use super::{geocode_with, no_result, number, text, GeocodeResult, Geocoder};
+use crate::{
+    ApiErrorKind, CoordSystem, ExtraArgs, RequestProfile, ResponseProfile, SuccessProfile,
+};
+
+/// Tencent's geocoder v1 API, answering in gcj02.
+#[derive(Debug, Clone)]
+pub struct TencentGeocoder {
+    pub req: RequestProfile,
+    res: ResponseProfile,
+}
+
+/// Tencent reports the precision level as a number
+const LEVELS: [&str; 11] = [
+    "城市",
+    "区县",
+    "乡镇",
+    "村庄",
+    "开发区",
+    "热点区域",
+    "道路",
+    "道路附属点",
+    "门址",
+    "小区大厦",
+    "POI",
+];
+
+impl TencentGeocoder {
+    pub const URL: &'static str = "https://apis.map.qq.com/ws/geocoder/v1/";
+
+    pub fn new(key: impl Into<String>) -> Self {
+        let params = json!({ "key": key.into() });
+        let url = Url::parse(Self::URL).expect("a valid url");
+
+        Self::from_profile(RequestProfile::new(
+            Method::GET,
+            url,
+            Some(params),
+            HeaderMap::new(),
+            None,
+        ))
+    }
+
+    /// Use a request profile of your own, e.g. to point at another host
+    pub fn from_profile(req: RequestProfile) -> Self {
+        let mut success = SuccessProfile::new("status", json!(0));
+        success.message_path = Some("message".into());
+        success.errors = [
+            (
+                ApiErrorKind::InvalidKey,
+                vec![json!(110), json!(111), json!(112), json!(113), json!(311)],
+            ),
+            (
+                ApiErrorKind::QuotaExceeded,
+                vec![json!(120), json!(121), json!(122)],
+            ),
+            (
+                ApiErrorKind::BadRequest,
+                vec![json!(301), json!(306), json!(310)],
+            ),
+            (ApiErrorKind::NoResult, vec![json!(347)]),
+        ]
+        .into();
+
+        let res = ResponseProfile {
+            success: Some(success),
+            ..Default::default()
+        };
+        Self { req, res }
+    }
+
+    pub fn parse(&self, body: &Value) -> Result<GeocodeResult> {
+        let result = &body["result"];
+        let (Some(lng), Some(lat)) = (
+            number(&result["location"]["lng"]),
+            number(&result["location"]["lat"]),
+        ) else {
+            return Err(no_result());
+        };
+
+        let level = result["level"]
+            .as_u64()
+            .and_then(|level| LEVELS.get((level as usize).checked_sub(1)?))
+            .map(|level| level.to_string());
+
+        // there is no formatted address, only its components
+        let components = &result["address_components"];
+        let formatted_address = ["province", "city", "district", "street", "street_number"]
+            .iter()
+            .filter_map(|key| text(&components[key]))
+            .fold(String::new(), |mut address, part| {
+                // municipalities repeat themselves as province and city
+                if !address.ends_with(&part) {
+                    address.push_str(&part);
+                }
+                address
+            });
+
+        Ok(GeocodeResult {
+            lng,
+            lat,
+            system: CoordSystem::Gcj02,
+            level,
+            // reliability goes from 1 to 10
+            confidence: number(&result["reliability"]).map(|r| r * 10.0),
+            formatted_address: Some(formatted_address).filter(|a| !a.is_empty()),
+            adcode: text(&result["ad_info"]["adcode"]),
+            raw: body.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for TencentGeocoder {
+    fn name(&self) -> &str {
+        "tencent"
+    }
+
+    async fn geocode(&self, address: &str, args: &ExtraArgs) -> Result<GeocodeResult> {
+        let parse = |body: &Value| self.parse(body);
+        geocode_with(&self.req, &self.res, "address", address, args, parse).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::geocoder::fixture;
+
+    #[test]
+    fn parse_should_work() {
+        let geocoder = TencentGeocoder::new("key");
+        let body = fixture("tencent.json");
+        geocoder.res.check_success(&body).unwrap();
+
+        let result = geocoder.parse(&body).unwrap();
+        assert_eq!((result.lng, result.lat), (104.137_36, 36.545_31));
+        assert_eq!(result.system, CoordSystem::Gcj02);
+        assert_eq!(result.level.as_deref(), Some("区县"));
+        assert_eq!(result.confidence, Some(70.0));
+        assert_eq!(
+            result.formatted_address.as_deref(),
+            Some("甘肃省白银市白银区")
+        );
+        assert_eq!(result.adcode.as_deref(), Some("620402"));
+    }
+
+    #[test]
+    fn errors_should_be_classified() {
+        let geocoder = TencentGeocoder::new("key");
+        let err = geocoder
+            .res
+            .check_success(&json!({"status": 121, "message": "此key每日调用量已达到上限"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::QuotaExceeded);
+
+        let err = geocoder
+            .res
+            .check_success(&json!({"status": 347, "message": "查询无结果"}))
+            .unwrap_err();
+        assert_eq!(err.kind, ApiErrorKind::NoResult);
+    }
+}
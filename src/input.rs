@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Context, Result};
+use calamine::{open_workbook_auto, Data, Reader};
+use csv::StringRecord;
+use std::path::Path;
+
+/// How to read an input table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputOptions {
+    /// The worksheet of a spreadsheet, defaults to the first one
+    pub sheet: Option<String>,
+
+    /// The 1-based row holding the headers, rows above it are skipped
+    pub header_row: usize,
+}
+
+/// An input table read from a CSV file or a spreadsheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Input {
+    pub headers: StringRecord,
+    pub records: Vec<StringRecord>,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            sheet: None,
+            header_row: 1,
+        }
+    }
+}
+
+impl Input {
+    /// Read a table, spreadsheets (.xlsx, .xls, .ods ...) are detected by extension
+    pub fn from_path(path: impl AsRef<Path>, options: &InputOptions) -> Result<Self> {
+        let path = path.as_ref();
+        if options.header_row == 0 {
+            return Err(anyhow!("header row is 1-based"));
+        }
+
+        let rows = if is_spreadsheet(path) {
+            read_spreadsheet(path, options)
+        } else {
+            read_csv(path)
+        }
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut rows = rows.into_iter().skip(options.header_row - 1);
+        let headers = rows
+            .next()
+            .ok_or_else(|| anyhow!("{} has no header row", path.display()))?;
+        let records = rows.filter(|r| r.iter().any(|f| !f.is_empty())).collect();
+
+        Ok(Self { headers, records })
+    }
+
+    /// Resolve a column given by header name or by 0-based index, names take precedence
+    pub fn column_index(&self, column: &str) -> Result<usize> {
+        if let Some(i) = self.headers.iter().position(|h| h == column) {
+            return Ok(i);
+        }
+
+        match column.parse::<usize>() {
+            Ok(i) if i < self.headers.len() => Ok(i),
+            _ => Err(anyhow!(
+                "column {} not found, available columns: {}",
+                column,
+                self.headers.iter().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+fn is_spreadsheet(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    matches!(
+        ext.as_deref(),
+        Some("xlsx" | "xlsm" | "xlsb" | "xls" | "xla" | "ods")
+    )
+}
+
+fn read_csv(path: &Path) -> Result<Vec<StringRecord>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    Ok(rdr.records().collect::<Result<_, _>>()?)
+}
+
+fn read_spreadsheet(path: &Path, options: &InputOptions) -> Result<Vec<StringRecord>> {
+    let mut workbook = open_workbook_auto(path)?;
+
+    let sheet = match &options.sheet {
+        Some(sheet) => sheet.clone(),
+        None => workbook
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("workbook has no sheets"))?,
+    };
+    let range = workbook
+        .worksheet_range(&sheet)
+        .with_context(|| format!("failed to read sheet {}", sheet))?;
+
+    // the range starts at the first used cell, pad it so row numbers match the sheet
+    let (top, _) = range.start().unwrap_or_default();
+    let padding = std::iter::repeat_n(StringRecord::new(), top as usize);
+
+    Ok(padding
+        .chain(
+            range
+                .rows()
+                .map(|row| row.iter().map(cell_to_string).collect()),
+        )
+        .collect())
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        // spreadsheets store integers as floats, don't turn codes like 620400 into 620400.0
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+        cell => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_and_xlsx_should_yield_same_records() {
+        let csv = Input::from_path("fixtures/resident_city.csv", &InputOptions::default()).unwrap();
+        let xlsx = Input::from_path("fixtures/resident.xlsx", &InputOptions::default()).unwrap();
+
+        assert_eq!(csv.headers, StringRecord::from(vec!["市"]));
+        assert_eq!(&csv.records[0][0], "白银市");
+        assert_eq!(xlsx.headers.get(0), Some("市"));
+        assert_eq!(xlsx.records[0].get(0), Some("白银市"));
+    }
+
+    #[test]
+    fn sheet_should_select_worksheet() {
+        let options = InputOptions {
+            sheet: Some("县驻地".into()),
+            ..Default::default()
+        };
+        let xlsx = Input::from_path("fixtures/resident.xlsx", &options).unwrap();
+        let csv =
+            Input::from_path("fixtures/resident_county.csv", &InputOptions::default()).unwrap();
+
+        assert_eq!(xlsx.records[0].get(0), csv.records[0].get(0));
+    }
+
+    #[test]
+    fn column_index_should_work() {
+        let input = Input {
+            headers: StringRecord::from(vec!["id", "县", "1"]),
+            records: vec![],
+        };
+
+        assert_eq!(input.column_index("县").unwrap(), 1);
+        assert_eq!(input.column_index("0").unwrap(), 0);
+        assert_eq!(input.column_index("1").unwrap(), 2);
+        assert!(input.column_index("市").is_err());
+        assert!(input.column_index("3").is_err());
+    }
+
+    #[test]
+    fn header_row_should_skip_rows_above() {
+        let options = InputOptions {
+            header_row: 2,
+            ..Default::default()
+        };
+        let csv = Input::from_path("fixtures/resident_city.csv", &options).unwrap();
+
+        assert_eq!(csv.headers, StringRecord::from(vec!["白银市"]));
+        assert_eq!(&csv.records[0][0], "定西市");
+    }
+}
@@ -1,13 +1,43 @@
+mod cache;
+mod checkpoint;
 pub mod cli;
 mod config;
+pub mod coords;
+mod diff;
+mod error;
+pub mod gazetteer;
+pub mod geocoder;
+mod input;
+mod output;
+mod progress;
+mod record;
+mod region;
+mod replay;
+mod report;
 mod utils;
 
+pub use cache::{is_secret_param, Cache, CacheStats, CachedResponse, SECRET_PARAMS};
+pub use checkpoint::Checkpoint;
 pub use cli::{parse_key_value, KeyValType};
-pub use utils::process_error_output;
+pub use coords::CoordSystem;
+pub use diff::{diff_json, DiffEntry};
+pub use error::{Aborted, ApiError, ApiErrorKind, RowError};
+pub use gazetteer::{Division, DivisionLevel, Gazetteer};
+pub use geocoder::{GeocodeResult, Geocoder};
+pub use input::{Input, InputOptions};
+pub use output::{cell_text, OrderedSink, OutputFormat, OutputSink, Row};
+pub use progress::Progress;
+pub use record::{redact_url, Exchange, ProfileRecorder, Recorder};
+pub use region::{OutOfRegion, Region, Regions};
+pub use replay::Replay;
+pub use report::{Latency, Report, RequestStats};
+pub use utils::{distance_in_metres, process_error_output};
 
 pub use config::{
-    get_status_text, GeoCodingConfig, GeoCodingProfile, LoadConfig, RequestProfile,
-    ResponseProfile, ValidateConfig,
+    get_status_text, Acceptance, AddressProfile, Assertion, BatchProfile, Chain, ChainAnswer,
+    ChainProfile, CheckProfile, CoordinateFields, GeoCodingConfig, GeoCodingProfile, LoadConfig,
+    LocationOrder, LocationProfile, NumberRange, Pager, PaginateProfile, Quality, QualityOp,
+    QualityRule, RequestProfile, ResolvedRequest, ResponseProfile, SuccessProfile, ValidateConfig,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -15,6 +45,8 @@ pub struct ExtraArgs {
     pub headers: Vec<(String, String)>,
     pub query: Vec<(String, String)>,
     pub body: Vec<(String, String)>,
+    /// Identifies the input row a request is made for in request logs, it is not sent
+    pub row_id: Option<String>,
 }
 
 impl IntoIterator for ExtraArgs {
@@ -0,0 +1,548 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use rust_xlsxwriter::Workbook;
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, io::Write};
+
+use crate::{CoordSystem, CoordinateFields};
+
+/// The file formats results can be written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+    Ndjson,
+    Geojson,
+    Xlsx,
+}
+
+/// A single output row, fields keep the order they were pushed in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Row(Vec<(String, Value)>);
+
+/// Fields written as a JSON object in the order they are listed, a `serde_json::Map`
+/// would sort them.
+struct Object<'a>(Vec<(&'a str, &'a Value)>);
+
+/// Somewhere rows are written to, `finish` must be called once all rows are written.
+pub trait OutputSink: Send {
+    fn write_row(&mut self, row: &Row) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Wraps a sink so rows finishing out of order are still written in input order.
+///
+/// Rows are buffered until every row before them was written or skipped.
+pub struct OrderedSink {
+    inner: Box<dyn OutputSink>,
+    next: usize,
+    pending: BTreeMap<usize, Option<Row>>,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::Geojson => "geojson",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    /// Create a sink writing this format to `writer`.
+    ///
+    /// `coordinates` names the fields holding the point of a row, it is required by GeoJSON.
+    pub fn sink(
+        &self,
+        writer: Box<dyn Write + Send>,
+        coordinates: Option<CoordinateFields>,
+    ) -> Result<Box<dyn OutputSink>> {
+        Ok(match self {
+            Self::Csv => Box::new(CsvSink::new(writer)),
+            Self::Json => Box::new(JsonSink::new(writer)),
+            Self::Ndjson => Box::new(NdjsonSink(writer)),
+            Self::Geojson => {
+                let coordinates = coordinates.ok_or_else(|| {
+                    anyhow!("geojson output requires `coordinates` in the response profile")
+                })?;
+                Box::new(GeoJsonSink::new(writer, coordinates))
+            }
+            Self::Xlsx => Box::new(XlsxSink::new(writer)),
+        })
+    }
+}
+
+impl Row {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push a field, replacing the value of an existing field with the same key
+    pub fn push(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        let (key, value) = (key.into(), value.into());
+        match self.0.iter_mut().find(|(k, _)| k == &key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    /// Push every field of a JSON object, e.g. the picked results of a query
+    pub fn extend(&mut self, obj: &Map<String, Value>) {
+        for (k, v) in obj {
+            self.push(k.as_str(), v.clone());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(k, _)| k.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    fn to_object(&self) -> Object<'_> {
+        Object(self.iter().collect())
+    }
+
+    /// Read a field as a number, accepting numeric strings as well
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key).and_then(value_to_f64)
+    }
+
+    /// Convert the coordinate fields from the system the provider answers in into `to`.
+    /// Rows without both coordinates are left as they are.
+    pub fn convert_coordinates(
+        &mut self,
+        fields: &CoordinateFields,
+        to: CoordSystem,
+    ) -> Result<()> {
+        let from = fields
+            .system
+            .ok_or_else(|| anyhow!("the coordinate system of the response is not configured"))?;
+
+        if let (Some(lng), Some(lat)) = (self.get_f64(&fields.lng), self.get_f64(&fields.lat)) {
+            let (lng, lat) = from.convert(lng, lat, to);
+            self.push(fields.lng.as_str(), lng);
+            self.push(fields.lat.as_str(), lat);
+        }
+        Ok(())
+    }
+}
+
+impl OrderedSink {
+    /// Create an ordered sink, `first` is the index of the first row expected
+    pub fn new(inner: Box<dyn OutputSink>, first: usize) -> Self {
+        Self {
+            inner,
+            next: first,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn write_at(&mut self, index: usize, row: Row) -> Result<()> {
+        self.pending.insert(index, Some(row));
+        self.flush_ready()
+    }
+
+    /// Mark a row which won't be written, so the rows after it aren't held back
+    pub fn skip(&mut self, index: usize) -> Result<()> {
+        self.pending.insert(index, None);
+        self.flush_ready()
+    }
+
+    /// Write whatever is still buffered, in order, and finish the inner sink
+    pub fn finish(&mut self) -> Result<()> {
+        for row in std::mem::take(&mut self.pending).into_values().flatten() {
+            self.inner.write_row(&row)?;
+        }
+        self.inner.finish()
+    }
+
+    fn flush_ready(&mut self) -> Result<()> {
+        while let Some(row) = self.pending.remove(&self.next) {
+            if let Some(row) = row {
+                self.inner.write_row(&row)?;
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+/// The text of a value as written into a cell, null becomes an empty cell
+pub fn cell_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+pub(crate) fn value_to_f64(v: &Value) -> Option<f64> {
+    v.as_f64()
+        .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// Columns are taken from the first row, later rows are aligned to them.
+struct CsvSink {
+    wtr: csv::Writer<Box<dyn Write + Send>>,
+    headers: Option<Vec<String>>,
+}
+
+impl CsvSink {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            wtr: csv::Writer::from_writer(writer),
+            headers: None,
+        }
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        let headers = match &self.headers {
+            Some(headers) => headers,
+            None => {
+                let headers = row.keys().map(String::from).collect::<Vec<_>>();
+                self.wtr.write_record(&headers)?;
+                self.headers.insert(headers)
+            }
+        };
+
+        let record = headers
+            .iter()
+            .map(|k| row.get(k).map(cell_text).unwrap_or_default())
+            .collect::<Vec<_>>();
+        self.wtr.write_record(record)?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+impl Serialize for Object<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in &self.0 {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+struct JsonSink {
+    writer: Box<dyn Write + Send>,
+    rows: Vec<Row>,
+}
+
+impl JsonSink {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer,
+            rows: vec![],
+        }
+    }
+}
+
+impl OutputSink for JsonSink {
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        self.rows.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let rows = self.rows.iter().map(Row::to_object).collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut self.writer, &rows)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct NdjsonSink(Box<dyn Write + Send>);
+
+impl OutputSink for NdjsonSink {
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        serde_json::to_writer(&mut self.0, &row.to_object())?;
+        writeln!(self.0)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+/// Rows become Point features, rows without valid coordinates get a null geometry.
+struct GeoJsonSink {
+    writer: Box<dyn Write + Send>,
+    coordinates: CoordinateFields,
+    rows: Vec<Row>,
+}
+
+#[derive(Serialize)]
+struct FeatureCollection<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    features: Vec<Feature<'a>>,
+}
+
+#[derive(Serialize)]
+struct Feature<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: Value,
+    properties: Object<'a>,
+}
+
+impl GeoJsonSink {
+    fn new(writer: Box<dyn Write + Send>, coordinates: CoordinateFields) -> Self {
+        Self {
+            writer,
+            coordinates,
+            rows: vec![],
+        }
+    }
+
+    fn feature<'a>(&self, row: &'a Row) -> Feature<'a> {
+        let (lng, lat) = (&self.coordinates.lng, &self.coordinates.lat);
+
+        let geometry = match (row.get_f64(lng), row.get_f64(lat)) {
+            (Some(lng), Some(lat)) => json!({"type": "Point", "coordinates": [lng, lat]}),
+            _ => Value::Null,
+        };
+        let properties = row.iter().filter(|(k, _)| k != lng && k != lat).collect();
+
+        Feature {
+            kind: "Feature",
+            geometry,
+            properties: Object(properties),
+        }
+    }
+}
+
+impl OutputSink for GeoJsonSink {
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        self.rows.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let features = self
+            .rows
+            .iter()
+            .map(|row| self.feature(row))
+            .collect::<Vec<_>>();
+        let collection = FeatureCollection {
+            kind: "FeatureCollection",
+            features,
+        };
+        serde_json::to_writer_pretty(&mut self.writer, &collection)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The workbook is only built in `finish`, since xlsx can't be streamed.
+struct XlsxSink {
+    writer: Box<dyn Write + Send>,
+    rows: Vec<Row>,
+}
+
+impl XlsxSink {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer,
+            rows: vec![],
+        }
+    }
+}
+
+impl OutputSink for XlsxSink {
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        self.rows.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+
+        let headers = self
+            .rows
+            .first()
+            .map(|row| row.keys().map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string(0, col as u16, header)?;
+        }
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let r = i as u32 + 1;
+            for (col, header) in headers.iter().enumerate() {
+                match row.get(header) {
+                    Some(Value::Number(n)) => {
+                        sheet.write_number(r, col as u16, n.as_f64().unwrap_or_default())?;
+                    }
+                    Some(v) => {
+                        sheet.write_string(r, col as u16, cell_text(v))?;
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        self.writer.write_all(&workbook.save_to_buffer()?)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn rows() -> Vec<Row> {
+        let mut first = Row::new();
+        first.push("地址", "白银市");
+        first.push("经度坐标", 104.14);
+        first.push("纬度坐标", "36.54");
+        first.push("地址结构", Value::Null);
+
+        let mut second = Row::new();
+        second.push("地址", "定西市");
+        second.push("地址结构", "城市");
+
+        vec![first, second]
+    }
+
+    fn write_all(format: OutputFormat) -> String {
+        let buf = Buffer::default();
+        let coordinates = CoordinateFields::new("经度坐标", "纬度坐标");
+        let mut sink = format
+            .sink(Box::new(buf.clone()), Some(coordinates))
+            .unwrap();
+        for row in rows() {
+            sink.write_row(&row).unwrap();
+        }
+        sink.finish().unwrap();
+        buf.text()
+    }
+
+    #[test]
+    fn csv_sink_should_align_rows_to_first_header() {
+        assert_eq!(
+            write_all(OutputFormat::Csv),
+            "地址,经度坐标,纬度坐标,地址结构\n白银市,104.14,36.54,\n定西市,,,城市\n"
+        );
+    }
+
+    #[test]
+    fn geojson_sink_should_write_points() {
+        let v: Value = serde_json::from_str(&write_all(OutputFormat::Geojson)).unwrap();
+
+        assert_eq!(
+            v["features"][0],
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [104.14, 36.54]},
+                "properties": {"地址": "白银市", "地址结构": null},
+            })
+        );
+        assert_eq!(v["features"][1]["geometry"], Value::Null);
+    }
+
+    #[test]
+    fn convert_coordinates_should_work() {
+        let fields = CoordinateFields::new("经度坐标", "纬度坐标").with_system(CoordSystem::Gcj02);
+        let [mut first, mut second] = rows().try_into().unwrap();
+
+        first
+            .convert_coordinates(&fields, CoordSystem::Wgs84)
+            .unwrap();
+        let (lng, lat) = (
+            first.get_f64("经度坐标").unwrap(),
+            first.get_f64("纬度坐标").unwrap(),
+        );
+        assert!((lng - 104.14).abs() > 1e-3);
+
+        let (lng, lat) = CoordSystem::Wgs84.convert(lng, lat, CoordSystem::Gcj02);
+        assert!((lng - 104.14).abs() < 1e-9 && (lat - 36.54).abs() < 1e-9);
+
+        let unchanged = second.clone();
+        second
+            .convert_coordinates(&fields, CoordSystem::Wgs84)
+            .unwrap();
+        assert_eq!(second, unchanged);
+    }
+
+    #[test]
+    fn ordered_sink_should_write_in_input_order() {
+        let buf = Buffer::default();
+        let mut sink = OrderedSink::new(
+            OutputFormat::Ndjson
+                .sink(Box::new(buf.clone()), None)
+                .unwrap(),
+            1,
+        );
+
+        let row = |i: usize| {
+            let mut row = Row::new();
+            row.push("行号", i);
+            row
+        };
+
+        sink.write_at(3, row(3)).unwrap();
+        sink.write_at(2, row(2)).unwrap();
+        assert_eq!(buf.text(), "");
+
+        sink.skip(1).unwrap();
+        assert_eq!(buf.text(), "{\"行号\":2}\n{\"行号\":3}\n");
+
+        sink.write_at(5, row(5)).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(buf.text(), "{\"行号\":2}\n{\"行号\":3}\n{\"行号\":5}\n");
+    }
+
+    #[test]
+    fn ndjson_sink_should_write_one_line_per_row() {
+        assert_eq!(
+            write_all(OutputFormat::Ndjson),
+            "{\"地址\":\"白银市\",\"经度坐标\":104.14,\"纬度坐标\":\"36.54\",\"地址结构\":null}\n{\"地址\":\"定西市\",\"地址结构\":\"城市\"}\n"
+        );
+    }
+}
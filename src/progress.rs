@@ -0,0 +1,140 @@
+use console::{Style, Term};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::RequestStats;
+
+/// Reports the progress of a batch run on stderr.
+///
+/// On a terminal a progress bar is redrawn in place, otherwise a plain line is
+/// printed every `interval`, so logs of non-interactive runs stay readable.
+pub struct Progress {
+    term: Term,
+    interactive: bool,
+    total: usize,
+    done: usize,
+    errors: usize,
+    resumed: usize,
+    start: Instant,
+    last_draw: Option<Instant>,
+    interval: Duration,
+    /// The requests sent over the network, the QPS is measured on them
+    requests: Arc<RequestStats>,
+}
+
+/// The window over which the current QPS is measured
+const QPS_WINDOW: Duration = Duration::from_secs(5);
+const BAR_WIDTH: usize = 30;
+
+impl Progress {
+    pub fn new(total: usize, requests: Arc<RequestStats>) -> Self {
+        Self {
+            term: Term::stderr(),
+            interactive: atty::is(atty::Stream::Stderr),
+            total,
+            done: 0,
+            errors: 0,
+            resumed: 0,
+            start: Instant::now(),
+            last_draw: None,
+            interval: Duration::from_secs(10),
+            requests,
+        }
+    }
+
+    /// Count rows completed by an earlier run, they don't add to the rate
+    pub fn resume(&mut self, rows: usize) {
+        self.done += rows;
+        self.resumed += rows;
+    }
+
+    /// Count a completed row
+    pub fn inc(&mut self, ok: bool) {
+        let now = Instant::now();
+        self.done += 1;
+        if !ok {
+            self.errors += 1;
+        }
+
+        let every = if self.interactive {
+            Duration::from_millis(100)
+        } else {
+            self.interval
+        };
+        if self
+            .last_draw
+            .is_none_or(|t| now.duration_since(t) >= every)
+        {
+            self.draw();
+            self.last_draw = Some(now);
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.draw();
+        if self.interactive {
+            let _ = self.term.write_line("");
+        }
+    }
+
+    fn draw(&self) {
+        let line = self.line();
+        if self.interactive {
+            let _ = self.term.clear_line();
+            let _ = self.term.write_str(&line);
+        } else {
+            let _ = self.term.write_line(&line);
+        }
+    }
+
+    fn line(&self) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = match elapsed {
+            e if e > 0.0 => (self.done - self.resumed) as f64 / e,
+            _ => 0.0,
+        };
+        let eta = match rate {
+            r if r > 0.0 => format_duration((self.total - self.done) as f64 / r),
+            _ => "-".into(),
+        };
+        let window = QPS_WINDOW.min(self.start.elapsed());
+        let sent = self.requests.count_since(Instant::now() - window);
+        let qps = sent as f64 / window.as_secs_f64().max(1.0);
+
+        let stats = format!(
+            "{}/{} {:.1} rows/s eta {} errors {} qps {:.1}",
+            self.done, self.total, rate, eta, self.errors, qps
+        );
+
+        if !self.interactive {
+            return stats;
+        }
+
+        let filled = match self.total {
+            0 => BAR_WIDTH,
+            total => BAR_WIDTH * self.done / total,
+        };
+        let bar = format!("{}{}", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled));
+        let errors = match self.errors {
+            0 => Style::new(),
+            _ => Style::new().red(),
+        };
+
+        format!(
+            "[{}] {}",
+            Style::new().cyan().apply_to(bar),
+            errors.apply_to(stats)
+        )
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        s if s >= 3600 => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use reqwest::{header::HeaderMap, Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::is_secret_param;
+
+/// Appends every request sent over the network and its response to a JSON lines file.
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+/// A recorder bound to the profile whose requests it records.
+#[derive(Debug, Clone)]
+pub struct ProfileRecorder {
+    recorder: Arc<Recorder>,
+    profile: String,
+}
+
+/// One line of a request log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// RFC 3339 time the request was sent at
+    pub timestamp: String,
+    pub profile: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub row_id: Option<String>,
+    pub method: String,
+    /// The resolved url, with secret params redacted
+    pub url: String,
+    /// The normalized request, see `Cache::key`
+    pub key: String,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+    pub latency_ms: f64,
+}
+
+impl Recorder {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open request log {}", path.display()))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, exchange: &Exchange) -> Result<()> {
+        let mut line = serde_json::to_string(exchange)?;
+        line.push('\n');
+
+        // a single write per line, so concurrent requests never interleave
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+impl ProfileRecorder {
+    pub fn new(recorder: Arc<Recorder>, profile: impl Into<String>) -> Self {
+        Self {
+            recorder,
+            profile: profile.into(),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &self,
+        row_id: Option<&str>,
+        method: &Method,
+        url: &Url,
+        key: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+        sent_at: SystemTime,
+        latency: Duration,
+    ) -> Result<()> {
+        let exchange = Exchange {
+            timestamp: rfc3339(sent_at),
+            profile: self.profile.clone(),
+            row_id: row_id.map(String::from),
+            method: method.to_string(),
+            url: redact_url(url),
+            key: key.to_string(),
+            status: status.as_u16(),
+            headers: headers
+                .iter()
+                .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+                .collect(),
+            body: body.to_string(),
+            latency_ms: latency.as_secs_f64() * 1000.0,
+        };
+
+        self.recorder.record(&exchange)
+    }
+}
+
+/// Replace the values of secret query params, so logs can be shared
+pub fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    let pairs = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if is_secret_param(&k) {
+                "REDACTED".into()
+            } else {
+                v.into_owned()
+            };
+            (k.into_owned(), v)
+        })
+        .collect::<Vec<_>>();
+
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.into()
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    let d = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (secs, millis) = (d.as_secs() as i64, d.subsec_millis());
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        millis
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redact_url_should_work() {
+        let url = Url::parse(
+            "https://api.map.baidu.com/geocoding/v3/?address=白银市&ak=secret&output=json",
+        )
+        .unwrap();
+
+        assert_eq!(
+            redact_url(&url),
+            "https://api.map.baidu.com/geocoding/v3/?address=%E7%99%BD%E9%93%B6%E5%B8%82&ak=REDACTED&output=json"
+        );
+    }
+
+    #[test]
+    fn rfc3339_should_work() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_792_376_005_123);
+        assert_eq!(rfc3339(time), "2026-10-19T02:13:25.123Z");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
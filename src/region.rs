@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::{f64::consts::PI, fmt, path::Path};
+
+use crate::cell_text;
+
+/// Metres per degree of latitude on the sphere `distance_in_metres` uses
+const METRES_PER_DEGREE: f64 = 6_371_008.8 * PI / 180.0;
+
+/// Region boundaries loaded from a GeoJSON file, e.g. the county polygons of a province.
+/// Features are found by their `name` or `adcode` property.
+#[derive(Debug, Clone, Default)]
+pub struct Regions {
+    regions: Vec<Region>,
+}
+
+/// A named region made of one or more polygons
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub adcode: Option<String>,
+    /// Each polygon is an outer ring followed by its holes, as `(lng, lat)` points
+    polygons: Vec<Vec<Vec<(f64, f64)>>>,
+}
+
+/// A point which fell outside the region its row expects
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutOfRegion {
+    pub region: String,
+    pub lng: f64,
+    pub lat: f64,
+    pub distance_m: f64,
+}
+
+impl Regions {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to open regions {}", path.display()))?;
+        let json = serde_json::from_str(&file)
+            .with_context(|| format!("invalid GeoJSON in {}", path.display()))?;
+        Self::from_geojson(&json).with_context(|| format!("invalid regions in {}", path.display()))
+    }
+
+    /// Read the Polygon and MultiPolygon features of a FeatureCollection or a single Feature
+    pub fn from_geojson(json: &Value) -> Result<Self> {
+        let features = match json["type"].as_str() {
+            Some("FeatureCollection") => json["features"]
+                .as_array()
+                .ok_or_else(|| anyhow!("a FeatureCollection needs `features`"))?
+                .iter()
+                .collect(),
+            Some("Feature") => vec![json],
+            _ => return Err(anyhow!("expected a FeatureCollection or a Feature")),
+        };
+
+        let mut regions = vec![];
+        for (i, feature) in features.into_iter().enumerate() {
+            let properties = &feature["properties"];
+            let Some(name) = properties["name"].as_str() else {
+                return Err(anyhow!("feature {} has no name", i));
+            };
+            let geometry = &feature["geometry"];
+            let polygons = match geometry["type"].as_str() {
+                Some("Polygon") => vec![polygon(&geometry["coordinates"])?],
+                Some("MultiPolygon") => geometry["coordinates"]
+                    .as_array()
+                    .ok_or_else(|| anyhow!("invalid MultiPolygon of {}", name))?
+                    .iter()
+                    .map(polygon)
+                    .collect::<Result<_>>()?,
+                // points and lines have no inside
+                _ => continue,
+            };
+            regions.push(Region {
+                name: name.to_string(),
+                adcode: Some(cell_text(&properties["adcode"])).filter(|code| !code.is_empty()),
+                polygons,
+            });
+        }
+
+        Ok(Self { regions })
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// The region with this name or adcode
+    pub fn get(&self, key: &str) -> Option<&Region> {
+        let key = key.trim();
+        self.regions
+            .iter()
+            .find(|region| region.name == key || region.adcode.as_deref() == Some(key))
+    }
+}
+
+fn polygon(coordinates: &Value) -> Result<Vec<Vec<(f64, f64)>>> {
+    let rings = coordinates
+        .as_array()
+        .filter(|rings| !rings.is_empty())
+        .ok_or_else(|| anyhow!("a polygon needs at least one ring"))?;
+
+    rings
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .ok_or_else(|| anyhow!("a ring is a list of positions"))?
+                .iter()
+                .map(
+                    |position| match (position[0].as_f64(), position[1].as_f64()) {
+                        (Some(lng), Some(lat)) => Ok((lng, lat)),
+                        _ => Err(anyhow!("invalid position {}", position)),
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+impl Region {
+    pub fn contains(&self, lng: f64, lat: f64) -> bool {
+        self.polygons.iter().any(|rings| {
+            let (outer, holes) = rings.split_first().expect("polygons have an outer ring");
+            ring_contains(outer, lng, lat) && !holes.iter().any(|h| ring_contains(h, lng, lat))
+        })
+    }
+
+    /// The distance from a point to the region in metres, 0 inside it
+    pub fn distance_m(&self, lng: f64, lat: f64) -> f64 {
+        if self.contains(lng, lat) {
+            return 0.0;
+        }
+
+        // flatten the earth around the point, good enough at region scale
+        let x_scale = METRES_PER_DEGREE * lat.to_radians().cos();
+        let to_local = |(x, y): (f64, f64)| ((x - lng) * x_scale, (y - lat) * METRES_PER_DEGREE);
+
+        self.polygons
+            .iter()
+            .flatten()
+            .flat_map(|ring| ring.windows(2))
+            .map(|edge| distance_to_segment(to_local(edge[0]), to_local(edge[1])))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// Even-odd ray casting
+fn ring_contains(ring: &[(f64, f64)], lng: f64, lat: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let ((xi, yi), (xj, yj)) = (ring[i], ring[j]);
+        if (yi > lat) != (yj > lat) && lng < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// The distance from the origin to the segment `a`-`b`
+fn distance_to_segment(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (-(a.0 * dx + a.1 * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a.0 + t * dx).hypot(a.1 + t * dy)
+}
+
+impl fmt::Display for OutOfRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.6},{:.6} is {:.0} m outside {}",
+            self.lng, self.lat, self.distance_m, self.region
+        )
+    }
+}
+
+impl std::error::Error for OutOfRegion {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn regions() -> Regions {
+        Regions::from_geojson(&json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {"name": "白银区", "adcode": 620402},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [
+                            [[104.0, 36.4], [104.4, 36.4], [104.4, 36.8], [104.0, 36.8], [104.0, 36.4]],
+                            [[104.1, 36.5], [104.2, 36.5], [104.2, 36.6], [104.1, 36.6], [104.1, 36.5]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"name": "会宁县"},
+                    "geometry": {
+                        "type": "MultiPolygon",
+                        "coordinates": [
+                            [[[105.0, 35.5], [105.5, 35.5], [105.5, 36.0], [105.0, 35.5]]]
+                        ]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"name": "市政府"},
+                    "geometry": {"type": "Point", "coordinates": [104.17, 36.55]}
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn regions_should_be_found_by_name_or_adcode() {
+        let regions = regions();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions.get("620402").unwrap().name, "白银区");
+        assert_eq!(regions.get("会宁县").unwrap().adcode, None);
+        assert!(regions.get("市政府").is_none());
+    }
+
+    #[test]
+    fn contains_should_respect_holes() {
+        let regions = regions();
+        let baiyin = regions.get("白银区").unwrap();
+        assert!(baiyin.contains(104.3, 36.7));
+        assert!(!baiyin.contains(104.15, 36.55));
+        assert!(!baiyin.contains(105.2, 35.6));
+        assert!(regions.get("会宁县").unwrap().contains(105.4, 35.6));
+    }
+
+    #[test]
+    fn distance_m_should_work() {
+        let baiyin = regions().get("白银区").cloned().unwrap();
+        assert_eq!(baiyin.distance_m(104.3, 36.7), 0.0);
+
+        // due north of the boundary the distance is along a meridian
+        let distance = baiyin.distance_m(104.2, 36.9);
+        let expected = crate::distance_in_metres(104.2, 36.8, 104.2, 36.9);
+        assert!((distance - expected).abs() < 1.0);
+
+        // inside the hole, whose sides are closer than its top and bottom
+        let distance = baiyin.distance_m(104.15, 36.55);
+        let expected = crate::distance_in_metres(104.15, 36.55, 104.1, 36.55);
+        assert!((distance - expected).abs() < 1.0);
+    }
+}
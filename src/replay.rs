@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::Response;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{cache::build_response, Exchange};
+
+/// Serves responses from a request log written by `Recorder` instead of the network.
+#[derive(Debug, Default)]
+pub struct Replay {
+    exchanges: HashMap<String, Exchange>,
+}
+
+impl Replay {
+    /// Load a request log, when a request was recorded more than once the last response wins
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("failed to open request log {}", path.display()))?;
+
+        let mut exchanges = HashMap::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(&line)
+                .with_context(|| format!("invalid line {} in {}", i + 1, path.display()))?;
+            exchanges.insert(exchange.key.clone(), exchange);
+        }
+
+        Ok(Self { exchanges })
+    }
+
+    pub fn len(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exchanges.is_empty()
+    }
+
+    /// The recorded response for a normalized request, see `Cache::key`
+    pub fn get(&self, key: &str) -> Result<Response> {
+        self.exchanges
+            .get(key)
+            .ok_or_else(|| anyhow!("no recorded response for request {}", key))?
+            .to_response()
+    }
+}
+
+impl Exchange {
+    /// Rebuild the recorded response as if it came from the network
+    pub fn to_response(&self) -> Result<Response> {
+        build_response(self.status, &self.headers, &self.body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ApiError, ApiErrorKind, ExtraArgs, GeoCodingConfig, LoadConfig};
+    use std::sync::Arc;
+
+    const CONFIG: &str = r#"
+geo_coding:
+  req:
+    url: https://api.map.baidu.com/geocoding/v3/
+    params:
+      ret_coordtype: gcj02ll
+      ak: any-key-works
+      output: json
+  res:
+    success:
+      path: status
+      equals: 0
+      message_path: message
+    pick_results:
+      result.location.lng: '经度坐标'
+      result.location.lat: '纬度坐标'
+      result.level: '地址结构'
+"#;
+
+    fn config() -> GeoCodingConfig {
+        let replay = Replay::open("fixtures/geo_coding.jsonl").unwrap();
+        GeoCodingConfig::from_yaml(CONFIG)
+            .unwrap()
+            .with_replay(Some(Arc::new(replay)))
+    }
+
+    #[tokio::test]
+    async fn replay_should_serve_recorded_responses() {
+        let config = config();
+        let profile = config.get_profile("geo_coding").unwrap();
+
+        let result = profile
+            .query_with_city(ExtraArgs::default(), "白银市")
+            .await
+            .unwrap();
+
+        assert_eq!(result["地址结构"], "政府机构");
+        assert_eq!(result["经度坐标"], 104.144_936_180_712_87);
+        assert_eq!(result["纬度坐标"], 36.551_071_573_938_55);
+    }
+
+    #[tokio::test]
+    async fn replay_should_keep_provider_errors() {
+        let config = config();
+        let profile = config.get_profile("geo_coding").unwrap();
+
+        let e = profile
+            .query_with_city(ExtraArgs::default(), "定西市")
+            .await
+            .unwrap_err();
+
+        let api_error = e.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(api_error.kind, ApiErrorKind::QuotaExceeded);
+    }
+
+    #[tokio::test]
+    async fn replay_should_fail_on_unrecorded_requests() {
+        let config = config();
+        let profile = config.get_profile("geo_coding").unwrap();
+
+        let e = profile
+            .query_with_city(ExtraArgs::default(), "兰州市")
+            .await
+            .unwrap_err();
+
+        assert!(e
+            .to_string()
+            .starts_with("no recorded response for request"));
+    }
+}
@@ -0,0 +1,178 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::RowError;
+
+/// A machine-readable summary of a batch run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub rows: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Rows taken from a checkpoint of an earlier run
+    pub resumed: usize,
+    /// Rows the provider found nothing for
+    pub empty_results: usize,
+    pub errors_by_kind: BTreeMap<String, usize>,
+    /// Errors reported by the provider, by its status code
+    pub provider_errors_by_status: BTreeMap<String, usize>,
+    /// Successful rows by the value of the profile's `level` field
+    pub results_by_level: BTreeMap<String, usize>,
+    /// Requests sent over the network, including retries, chain fallbacks, address
+    /// formulations and further pages. Cached and replayed responses are not requests.
+    pub requests: usize,
+    pub latency_ms: Latency,
+
+    #[serde(skip)]
+    latencies: Vec<f64>,
+}
+
+/// Every request the profiles of a run sent over the network, with when it was answered
+/// and how long it took. Shared by the profiles through `RequestProfile::stats`.
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    sent: Mutex<Vec<(Instant, Duration)>>,
+}
+
+/// Latency percentiles in milliseconds over every request sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Latency {
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Report {
+    pub fn resume(&mut self) {
+        self.rows += 1;
+        self.succeeded += 1;
+        self.resumed += 1;
+    }
+
+    pub fn success(&mut self, level: Option<&str>) {
+        self.rows += 1;
+        self.succeeded += 1;
+
+        let level = level.filter(|l| !l.is_empty()).unwrap_or("unknown");
+        *self.results_by_level.entry(level.into()).or_default() += 1;
+    }
+
+    pub fn failure(&mut self, error: &RowError) {
+        self.rows += 1;
+        self.failed += 1;
+
+        *self.errors_by_kind.entry(error.kind.clone()).or_default() += 1;
+        if error.kind == "no_result" {
+            self.empty_results += 1;
+        }
+        if let Some(status) = &error.status {
+            let status = match status {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            *self.provider_errors_by_status.entry(status).or_default() += 1;
+        }
+    }
+
+    /// Compute the latency percentiles, call once every row was recorded
+    pub fn finish(&mut self) {
+        let latencies = &mut self.latencies;
+        if latencies.is_empty() {
+            return;
+        }
+        latencies.sort_by(|a, b| a.total_cmp(b));
+
+        let percentile = |p: f64| {
+            let i = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len());
+            latencies[i - 1]
+        };
+        self.latency_ms = Latency {
+            mean: latencies.iter().sum::<f64>() / latencies.len() as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: latencies[latencies.len() - 1],
+        };
+    }
+
+    /// Record the latency of every request sent, call once for the whole run
+    pub fn requests(&mut self, latencies: &[Duration]) {
+        self.requests += latencies.len();
+        self.latencies
+            .extend(latencies.iter().map(|d| d.as_secs_f64() * 1000.0));
+    }
+}
+
+impl RequestStats {
+    /// Count a request which took `latency` to answer
+    pub fn record(&self, latency: Duration) {
+        let mut sent = self.sent.lock().unwrap();
+        sent.push((Instant::now(), latency));
+    }
+
+    /// The number of requests answered since `since`
+    pub fn count_since(&self, since: Instant) -> usize {
+        let sent = self.sent.lock().unwrap();
+        sent.iter().rev().take_while(|(at, _)| *at >= since).count()
+    }
+
+    pub fn latencies(&self) -> Vec<Duration> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter().map(|(_, latency)| *latency).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn report_should_work() {
+        let mut report = Report::default();
+        let ms = |ms: &[u64]| {
+            ms.iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        };
+
+        report.resume();
+        report.success(Some("门址"));
+        report.success(Some("城市"));
+        report.failure(&RowError {
+            kind: "quota_exceeded".into(),
+            http_status: Some(200),
+            status: Some(json!(302)),
+            message: "天配额超限".into(),
+        });
+        report.requests(&ms(&[10, 30, 20, 40]));
+        report.finish();
+
+        let v = serde_json::to_value(&report).unwrap();
+        assert_eq!(v["rows"], json!(4));
+        assert_eq!(v["succeeded"], json!(3));
+        assert_eq!(v["requests"], json!(4));
+        assert_eq!(v["provider_errors_by_status"], json!({"302": 1}));
+        assert_eq!(v["results_by_level"], json!({"门址": 1, "城市": 1}));
+        assert_eq!(report.latency_ms.p50, 20.0);
+        assert_eq!(report.latency_ms.max, 40.0);
+        assert_eq!(report.latency_ms.mean, 25.0);
+    }
+
+    #[test]
+    fn request_stats_should_work() {
+        let stats = RequestStats::default();
+        stats.record(Duration::from_millis(10));
+        let since = Instant::now();
+        stats.record(Duration::from_millis(20));
+
+        assert_eq!(stats.count_since(since), 1);
+        assert_eq!(stats.latencies(), [10, 20].map(Duration::from_millis));
+    }
+}
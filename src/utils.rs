@@ -18,3 +18,15 @@ pub fn process_error_output(error: Result<()>) -> Result<()> {
 
     Ok(())
 }
+
+/// Great-circle distance in metres between two points given in degrees.
+pub fn distance_in_metres(lng1: f64, lat1: f64, lng2: f64, lat2: f64) -> f64 {
+    const EARTH_RADIUS: f64 = 6_371_008.8;
+
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (lng2 - lng1).to_radians();
+
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}